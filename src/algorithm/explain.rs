@@ -0,0 +1,114 @@
+use data::*;
+
+use super::generator::{Backtracker, SearchResult};
+
+/// Find a minimal set of entries that make the domain unsatisfiable on
+/// their own: leaving out any one of them makes it satisfiable.
+/// Returns None when the domain is satisfiable.
+pub fn explain(domain: &Domain) -> Option<Vec<Entry>> {
+    let entries = domain.entries();
+    let conflict = quick_xplain(entries.len(), |subset| {
+        let selected: Vec<Entry> = subset.iter().map(|&idx| entries[idx]).collect();
+        is_satisfiable(&domain.with_entries(&selected))
+    })?;
+    Some(conflict.into_iter().map(|idx| entries[idx]).collect())
+}
+
+fn is_satisfiable(domain: &Domain) -> bool {
+    match Backtracker::new(domain).generate() {
+        SearchResult::Solution(_) => true,
+        SearchResult::Exhausted => false,
+        SearchResult::LimitReached => unreachable!("no limits were set"),
+    }
+}
+
+/// QuickXplain (Junker, 2004): find a minimal subset of the items
+/// 0..num_items that is inconsistent, given a consistency check that is
+/// monotone (subsets of consistent sets are consistent).
+/// Returns None when all items together are consistent.
+fn quick_xplain<F>(num_items: usize, mut is_consistent: F) -> Option<Vec<usize>>
+    where F: FnMut(&[usize]) -> bool
+{
+    let items: Vec<usize> = (0..num_items).collect();
+    if is_consistent(&items) {
+        return None;
+    }
+    Some(qx(&mut is_consistent, &[], false, &items))
+}
+
+/// Minimal subset of items that is inconsistent together with background.
+/// `added` tells whether the background changed since the last check.
+fn qx<F>(is_consistent: &mut F,
+         background: &[usize],
+         added: bool,
+         items: &[usize])
+         -> Vec<usize>
+    where F: FnMut(&[usize]) -> bool
+{
+    if added && !is_consistent(background) {
+        return Vec::new();
+    }
+    if items.len() <= 1 {
+        return items.to_vec();
+    }
+
+    let (left, right) = items.split_at(items.len() / 2);
+
+    let with_left = [background, left].concat();
+    let right_conflict = qx(is_consistent, &with_left, !left.is_empty(), right);
+
+    let with_right = [background, &right_conflict].concat();
+    let left_conflict = qx(is_consistent, &with_right, !right_conflict.is_empty(), left);
+
+    [left_conflict, right_conflict].concat()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cat::*;
+    use failure::ResultExt;
+    use json;
+
+    #[test]
+    fn test_quick_xplain() {
+        // inconsistent when it contains 3 and 7, or 5
+        let conflict = quick_xplain(10, |items| {
+            let both = items.contains(&3) && items.contains(&7);
+            !(both || items.contains(&5))
+        });
+        assert_eq!(conflict, Some(vec![5]));
+
+        let mut conflict = quick_xplain(10, |items| {
+            !(items.contains(&3) && items.contains(&7))
+        }).unwrap();
+        conflict.sort();
+        assert_eq!(conflict, [3, 7]);
+
+        assert_eq!(quick_xplain(10, |_| true), None);
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+        assert_eq!(explain(&domain), None);
+
+        let find_key = |name: &str| {
+            domain.keys.enumerate().find(|&(_, n)| n == name).unwrap().0
+        };
+        let (lower, upper) = (find_key("1/lower"), find_key("1/upper"));
+        let a = domain.values.enumerate().find(|&(_, n)| n == "a").unwrap().0;
+
+        // 1/lower only takes lowercase values, which map 1/upper to
+        // uppercase values.
+        domain.key_restrictions[upper] = Restriction::Only(vec![a]);
+        let mut conflict = explain(&domain).unwrap();
+        conflict.sort();
+        assert_eq!(conflict, [
+            Entry::Restriction(lower),
+            Entry::Restriction(upper),
+            Entry::Constraint(lower, upper),
+        ]);
+    }
+}
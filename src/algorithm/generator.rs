@@ -1,29 +1,91 @@
 use data::*;
 use cat::*;
+use cat::ops::*;
 
-use Result;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 
 use std::collections::HashSet;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::iter::FromIterator;
+use std::time::{Duration, Instant};
+
+use super::heuristics::{VariableOrder, ValueOrder, Mrv, RangeOrder};
+use super::restarts::Restarts;
+use super::observer::{Event, SearchObserver, Silent};
+use super::stats::{Limits, SearchStats};
 
 pub struct Backtracker<'d> {
     domain_walker: DomainWalker<'d>,
     stack: Vec<Step>,
     unassigned: HashSet<Num<Key>>,
+    state: State,
+    // only nogoods up to this length are learned
+    max_nogood_len: usize,
+    variable_order: Box<dyn VariableOrder>,
+    value_order: Box<dyn ValueOrder>,
+    // when set, values are shuffled before the value order is applied
+    rng: Option<StdRng>,
+    restarts: Restarts,
+    // backtracks since the last restart
+    num_backtracks: usize,
+    stats: SearchStats,
+    limits: Limits,
+    // set from elsewhere, e.g. another thread, to stop the search
+    stop: Option<&'d AtomicBool>,
+    // restarting after a solution was reported would report it again
+    found_solution: bool,
+    observer: Box<dyn SearchObserver + 'd>,
+}
+
+/// Longer nogoods rarely prune anything, so by default they are not kept.
+pub const DEFAULT_MAX_NOGOOD_LEN: usize = 12;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Searching,
+    /// The current position is a solution that was already reported.
+    AtSolution,
+    Exhausted,
+}
+
+/// Outcome of a search.
+pub enum SearchResult {
+    /// A complete assignment satisfying all constraints.
+    Solution(Table<Key, Num<Value>>),
+    /// The search space was exhausted without finding a (further) solution.
+    Exhausted,
+    /// The search stopped at one of its limits. Raising the limits and
+    /// calling `generate` again resumes it.
+    LimitReached,
 }
 
 struct Step {
     key_num: Num<Key>,
     values: Vec<Num<Value>>,
     pos: usize,
+    // walker state before the current value was assigned
+    checkpoint: Checkpoint,
+    // earlier keys that were to blame for the values that failed so far
+    conflicts: Vec<Num<Key>>,
+    // whether a solution was found below this step, or its remaining
+    // values were split off; either way its conflicts are not a nogood
+    solved: bool,
 }
 
 impl Step {
-    fn new(key_num: Num<Key>, values: Vec<Num<Value>>) -> Self {
+    fn new(key_num: Num<Key>, values: Vec<Num<Value>>, checkpoint: Checkpoint)
+        -> Self
+    {
         Step {
-            key_num: key_num,
-            values: values,
+            key_num,
+            values,
             pos: 0,
+            checkpoint,
+            conflicts: Vec::new(),
+            solved: false,
         }
     }
 
@@ -31,7 +93,7 @@ impl Step {
         self.pos += 1;
     }
 
-    fn has_next(&mut self) -> bool {
+    fn has_next(&self) -> bool {
         self.pos < self.values.len() - 1
     }
 
@@ -45,60 +107,325 @@ impl Step {
 
 impl<'d> Backtracker<'d> {
     pub fn new(domain: &'d Domain) -> Self {
+        let mut domain_walker = DomainWalker::new(domain);
+        // prune the ranges before searching; a wipeout here will show up as
+        // an invalid state.
+        domain_walker.propagate();
+
         Backtracker {
-            domain_walker: DomainWalker::new(domain),
+            domain_walker,
             stack: Vec::with_capacity(domain.keys.count().as_usize()),
             unassigned: HashSet::from_iter(domain.keys.nums()),
+            state: State::Searching,
+            max_nogood_len: DEFAULT_MAX_NOGOOD_LEN,
+            variable_order: Box::new(Mrv),
+            value_order: Box::new(RangeOrder),
+            rng: None,
+            restarts: Restarts::Never,
+            num_backtracks: 0,
+            stats: SearchStats::default(),
+            limits: Limits::none(),
+            stop: None,
+            found_solution: false,
+            observer: Box::new(Silent),
+        }
+    }
+
+    pub fn set_variable_order(&mut self, variable_order: Box<dyn VariableOrder>) {
+        self.variable_order = variable_order;
+    }
+
+    pub fn set_value_order(&mut self, value_order: Box<dyn ValueOrder>) {
+        self.value_order = value_order;
+    }
+
+    /// Report the events of the search to given observer.
+    pub fn set_observer(&mut self, observer: Box<dyn SearchObserver + 'd>) {
+        self.observer = observer;
+    }
+
+    /// Shuffle values in an order determined by given seed before applying
+    /// the value order, so that it breaks the ties at random.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Some(StdRng::seed_from_u64(seed));
+    }
+
+    /// Restarts only apply until the first solution is found; after that the
+    /// search runs to completion so that no solution is reported twice.
+    pub fn set_restarts(&mut self, restarts: Restarts) {
+        self.restarts = restarts;
+    }
+
+    /// Stop the search once it reaches any of the limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Stop the search, as if a limit was reached, once the flag is set.
+    pub fn set_stop_flag(&mut self, stop: &'d AtomicBool) {
+        self.stop = Some(stop);
+    }
+
+    /// The work done by the search so far.
+    pub fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    pub fn set_max_nogood_len(&mut self, max_nogood_len: usize) {
+        self.max_nogood_len = max_nogood_len;
+    }
+
+    /// Add previously learned nogoods. Should be called before searching.
+    pub fn add_nogoods<I>(&mut self, nogoods: I)
+        where I: IntoIterator<Item = Nogood>
+    {
+        assert!(self.stack.is_empty(), "search already started");
+        for nogood in nogoods {
+            self.domain_walker.add_nogood(nogood);
         }
+        self.domain_walker.propagate();
     }
 
-    pub fn generate(&mut self) -> Result<()> {
+    /// Fix the given assignments before searching. Pinned keys are left out
+    /// of the search, so they keep their value in every solution.
+    pub fn pin<I>(&mut self, pins: I)
+        where I: IntoIterator<Item = Assignment>
+    {
+        assert!(self.stack.is_empty(), "search already started");
+        for assignment in pins {
+            self.assign(assignment);
+        }
+    }
+
+    /// Hand over the values that are still to be tried for the key nearest
+    /// to the root, so that another search can take them on. Every value
+    /// comes with the assignments leading to it. This search skips them from
+    /// then on, and no longer restarts, since a restart would search them
+    /// again. Returns nothing when no such values are left.
+    pub fn split_off(&mut self) -> Vec<Vec<Assignment>> {
+        let idx = match self.stack.iter().position(|step| step.has_next()) {
+            Some(idx) => idx,
+            None => return Vec::new(),
+        };
+        let path: Vec<Assignment> = self.stack[..idx].iter()
+            .map(|step| step.assignment())
+            .collect();
+
+        let step = &mut self.stack[idx];
+        let key_num = step.key_num;
+        let values = step.values.split_off(step.pos + 1);
+        // Nothing can be learned from a key whose values were not all tried
+        // here, nor from the keys above it.
+        for step in self.stack[..=idx].iter_mut() {
+            step.solved = true;
+        }
+        self.restarts = Restarts::Never;
+
+        values.into_iter().map(|value_num| {
+            let mut item = path.clone();
+            item.push(Assignment { key_num, value_num });
+            item
+        }).collect()
+    }
+
+    /// The nogoods that are known so far, including the learned ones.
+    pub fn nogoods(&self) -> &[Nogood] {
+        self.domain_walker.nogoods().nogoods()
+    }
+
+    /// Search for the next solution. Calling this again after a solution was
+    /// found resumes the search, so that all solutions can be enumerated.
+    pub fn generate(&mut self) -> SearchResult {
+        let start = Instant::now();
+        let result = self.search(start);
+        self.stats.elapsed += start.elapsed();
+        result
+    }
+
+    fn search(&mut self, start: Instant) -> SearchResult {
+        match self.state {
+            State::Searching => (),
+            State::Exhausted => return SearchResult::Exhausted,
+            State::AtSolution => {
+                // moving on from the solution tries a value as well
+                if self.limit_reached(start) {
+                    return SearchResult::LimitReached;
+                }
+                self.state = State::Searching;
+                // Every assignment on the stack is 'to blame' for the
+                // solution, so that no part of the tree gets skipped.
+                for step in self.stack.iter_mut() {
+                    step.solved = true;
+                }
+                let all = self.stack.iter().map(|step| step.key_num).collect();
+                if !self.backjump(all) {
+                    return self.exhausted();
+                }
+            }
+        }
+
         loop {
-            while !self.domain_walker.valid_state() {
+            if let Some(key_num) = self.domain_walker.wipeout() {
                 // reached invalid state; backtrack
-                println!("{:?}", self.domain_walker.mapping());
-                println!("INVALID");
+                if self.limit_reached(start) {
+                    return SearchResult::LimitReached;
+                }
+                let conflict = self.domain_walker.explain_range(key_num);
+                self.variable_order.on_wipeout(key_num, &conflict);
+                self.notify(Event::Wipeout { key_num, conflict: &conflict });
+                self.stats.wipeouts += 1;
 
-                self.next()?;
+                if !self.backjump(conflict) {
+                    return self.exhausted();
+                }
+                self.num_backtracks += 1;
+                self.stats.backtracks += 1;
+                if self.restart_due() {
+                    self.restart();
+                }
+                continue;
             }
 
             if let Some(key_num) = self.next_key() {
+                if self.limit_reached(start) {
+                    return SearchResult::LimitReached;
+                }
                 self.descend(key_num);
             } else {
                 // all keys assigned! We are done.
-                println!("{:?}", self.domain_walker.mapping());
-                return Ok(());
+                let mapping = self.domain_walker.mapping().map(|v| v.unwrap());
+                self.notify(Event::Solution(&mapping));
+                self.state = State::AtSolution;
+                self.found_solution = true;
+                return SearchResult::Solution(mapping);
             }
         }
     }
 
-    fn next_key(&self) -> Option<Num<Key>> {
-        // Select most constrained key first for fail-first strategy
-        self.unassigned.iter().cloned().min_by_key(|&key_num| {
-            self.domain_walker.range(key_num).accepted().len()
-        })
-        // self.unassigned.iter().cloned().min()
+    /// Whether the search may not go on, counting the time spent since the
+    /// current call to `generate` started.
+    fn limit_reached(&self, start: Instant) -> bool {
+        if self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+            return true;
+        }
+        let running = match self.limits.time {
+            Some(_) => start.elapsed(),
+            None => Duration::from_secs(0),
+        };
+        self.limits.reached(&self.stats, running)
+    }
+
+    fn restart_due(&self) -> bool {
+        if self.found_solution {
+            return false;
+        }
+        match self.restarts.cutoff(self.stats.restarts) {
+            Some(cutoff) => self.num_backtracks >= cutoff,
+            None => false,
+        }
     }
 
-    fn next(&mut self) -> Result<()> {
-        while !self.current_step().has_next() {
+    /// Abandon the current branch and start over from the root. The learned
+    /// nogoods are kept, so the search does not simply repeat itself.
+    fn restart(&mut self) {
+        while !self.stack.is_empty() {
             self.ascend();
         }
+        // nogoods learned on the way may prune the root ranges
+        self.domain_walker.propagate();
+        self.notify(Event::Restart);
+        self.stats.restarts += 1;
+        self.num_backtracks = 0;
+    }
+
+    fn exhausted(&mut self) -> SearchResult {
+        self.state = State::Exhausted;
+        SearchResult::Exhausted
+    }
 
-        if !self.stack.is_empty() {
-            self.goto_next_sibling();
-            return Ok(());
+    /// Count the solutions that have not been visited yet.
+    pub fn count_solutions(&mut self) -> usize {
+        let mut count = 0;
+        while let SearchResult::Solution(_) = self.generate() {
+            count += 1;
         }
+        count
+    }
 
-        bail!("no more candidates")
+    fn next_key(&mut self) -> Option<Num<Key>> {
+        self.variable_order.select(&self.domain_walker, &self.unassigned)
+    }
+
+    /// Conflict-directed backjumping: jump back to the most recent
+    /// assignment in the conflict set and move on to its next value,
+    /// skipping the assignments in between as they are not to blame.
+    /// Returns false when there is nothing left to try.
+    fn backjump(&mut self, mut conflict: Vec<Num<Key>>) -> bool {
+        loop {
+            while !self.stack.is_empty() {
+                let key_num = self.current_step().key_num;
+                if conflict.contains(&key_num) {
+                    break;
+                }
+                self.ascend();
+            }
+
+            if self.stack.is_empty() {
+                return false;
+            }
+
+            let step = self.current_step();
+            for &key_num in conflict.iter() {
+                if key_num != step.key_num && !step.conflicts.contains(&key_num) {
+                    step.conflicts.push(key_num);
+                }
+            }
+
+            if step.has_next() {
+                let depth = self.stack.len();
+                self.notify(Event::Backtrack { depth });
+                self.goto_next_sibling();
+                return true;
+            }
+
+            // all values for this key failed; blame the union of their
+            // conflicts.
+            conflict = mem::take(&mut step.conflicts);
+            if !step.solved {
+                self.learn(&conflict);
+            }
+            self.ascend();
+        }
+    }
+
+    /// Remember that the current assignments of given keys do not lead to a
+    /// solution.
+    fn learn(&mut self, keys: &[Num<Key>]) {
+        if keys.is_empty() || keys.len() > self.max_nogood_len {
+            return;
+        }
+
+        let mapping = self.domain_walker.mapping();
+        let nogood = Nogood::new(keys.iter().map(|&key_num| {
+            Assignment { key_num, value_num: mapping[key_num].unwrap() }
+        }).collect());
+        self.domain_walker.add_nogood(nogood);
     }
 
     fn descend(&mut self, key_num: Num<Key>) {
-        let range = self.domain_walker.range_for(key_num).iter().cloned().collect();
-        let step = Step::new(key_num, range);
+        let mut range = self.domain_walker.range_for(key_num).to_vec();
+        if let Some(ref mut rng) = self.rng {
+            range.shuffle(rng);
+        }
+        self.value_order.order(&self.domain_walker, key_num, &mut range);
+        let checkpoint = self.domain_walker.checkpoint();
+        let mut step = Step::new(key_num, range, checkpoint);
+        // the values that were rejected before get blamed as well
+        step.conflicts = self.domain_walker.explain_range(key_num);
         self.stack.push(step);
+        self.stats.max_depth = self.stats.max_depth.max(self.stack.len());
         self.assign_pos();
-    }   
+    }
 
     fn ascend(&mut self) {
         self.unassign_pos();
@@ -114,36 +441,49 @@ impl<'d> Backtracker<'d> {
 
     fn assign_pos(&mut self) {
         let a = self.current_step().assignment();
+        self.stats.nodes += 1;
         self.assign(a);
     }
 
     fn unassign_pos(&mut self) {
         let a = self.current_step().assignment();
+        let checkpoint = self.current_step().checkpoint;
+        self.domain_walker.restore(checkpoint);
         self.unassign(a);
     }
 
-    fn current_step<'a>(&'a mut self) -> &'a mut Step {
+    fn current_step(&mut self) -> &mut Step {
         let idx = self.stack.len() - 1;
         &mut self.stack[idx]
     }
 
     fn assign(&mut self, assignment: Assignment) {
         self.domain_walker.assign(assignment.key_num, assignment.value_num);
+        self.domain_walker.propagate_from(assignment.key_num);
         self.unassigned.remove(&assignment.key_num);
+        self.notify(Event::Assign(assignment));
     }
 
     fn unassign(&mut self, assignment: Assignment) {
         self.domain_walker.unassign(assignment.key_num);
         self.unassigned.insert(assignment.key_num);
+        self.notify(Event::Unassign(assignment));
+    }
+
+    fn notify(&mut self, event: Event) {
+        self.observer.notify(self.domain_walker.domain(), &event);
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use super::super::heuristics::{LeastConstraining, VariableHeuristic};
+    use super::super::stats::Limits;
     use failure::ResultExt;
-    use cat::ops::*;
     use json;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
 
     fn mapping_valid(mapping: &Table<Key, Num<Value>>, domain: &Domain) -> bool {
         // TODO: maybe give a reason
@@ -152,15 +492,33 @@ mod test {
                 return false;
             }
 
-            for (other_key, &other_value) in mapping.enumerate() {
-                let restrictor = &domain.constraint_table[key_num][other_key];
-                if !restrictor[value_num].allows(other_value) {
+            for (other_key, restrictor) in domain.constraint_table.targets(key_num) {
+                if !restrictor[value_num].allows(mapping[*other_key]) {
+                    return false;
+                }
+            }
+        }
+
+        for group in domain.all_different.iter() {
+            for &key_num in group.iter() {
+                let value_num = mapping[key_num];
+                let count = group.iter()
+                    .filter(|&&other| mapping[other] == value_num)
+                    .count();
+                if domain.group_capacity(value_num).is_some_and(|c| count > c) {
                     return false;
                 }
             }
         }
 
-        return true;
+        for c in domain.cardinality.iter() {
+            let count = mapping.enumerate().filter(|&(_, &v)| v == c.value).count();
+            if count < c.min || c.max.is_some_and(|max| count > max) {
+                return false;
+            }
+        }
+
+        true
     }
 
 
@@ -171,10 +529,459 @@ mod test {
             .context("Could not parse domain").unwrap();
         
         let mut g = Backtracker::new(&domain);
-        g.generate().unwrap();
-        // TODO: implement mapmaybe?
-        let mapping = g.domain_walker.mapping().map(|e| e.unwrap());
+        match g.generate() {
+            SearchResult::Solution(mapping) => {
+                assert!(mapping_valid(&mapping, &domain))
+            }
+            _ => panic!("no solution found"),
+        }
+    }
 
-        assert!(mapping_valid(&mapping, &domain))
+    #[test]
+    fn test_enumerate() {
+        let domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+
+        let mut g = Backtracker::new(&domain);
+        let mut solutions = Vec::new();
+        while let SearchResult::Solution(mapping) = g.generate() {
+            assert!(mapping_valid(&mapping, &domain));
+            assert!(!solutions.contains(&mapping));
+            solutions.push(mapping);
+        }
+        // the lowercase keys are a permutation of abc; the uppercase keys
+        // follow from them.
+        assert_eq!(solutions.len(), 6);
+
+        // an exhausted search stays exhausted
+        match g.generate() {
+            SearchResult::Solution(_) => panic!("solution after exhaustion"),
+            SearchResult::Exhausted => (),
+            SearchResult::LimitReached => panic!("limit reached without limits"),
+        }
+        assert_eq!(g.stats().max_depth, 6);
     }
+
+    #[test]
+    fn test_limits() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for _ in 0..30 {
+            let domain = random_colouring(&mut rng, 14, 3, 0.35);
+            let count = Backtracker::new(&domain).count_solutions();
+
+            // raising the limit after it was reached resumes the search
+            let mut g = Backtracker::new(&domain);
+            let mut limits = Limits { nodes: Some(0), ..Limits::none() };
+            let mut found = 0;
+            loop {
+                g.set_limits(limits);
+                match g.generate() {
+                    SearchResult::Solution(_) => found += 1,
+                    SearchResult::Exhausted => break,
+                    SearchResult::LimitReached => {
+                        assert_eq!(Some(g.stats().nodes), limits.nodes);
+                        limits.nodes = Some(g.stats().nodes + 5);
+                    }
+                }
+            }
+            assert_eq!(found, count);
+
+            let mut g = Backtracker::new(&domain);
+            g.set_limits(Limits { backtracks: Some(3), ..Limits::none() });
+            while let SearchResult::Solution(_) = g.generate() {}
+            assert!(g.stats().backtracks <= 3);
+        }
+
+        let domain = json::read_config("sudoku.json")
+            .context("Could not parse domain").unwrap();
+        let mut g = Backtracker::new(&domain);
+        g.set_limits(Limits { time: Some(Duration::from_secs(0)), ..Limits::none() });
+        match g.generate() {
+            SearchResult::LimitReached => (),
+            _ => panic!("search went past its time limit"),
+        }
+        assert_eq!(g.stats().nodes, 0);
+    }
+
+    /// A domain with random binary constraints, small enough to brute force.
+    fn random_domain<R: Rng>(rng: &mut R,
+                             num_keys: usize,
+                             num_values: usize,
+                             density: f64)
+                             -> Domain
+    {
+        let values: Table<Value, String> = Table::from_vec(
+            (0..num_values).map(|v| v.to_string()).collect());
+        let keys: Table<Key, String> = Table::from_vec(
+            (0..num_keys).map(|k| k.to_string()).collect());
+
+        let random_subset = |rng: &mut R| -> Vec<Num<Value>> {
+            values.nums().filter(|_| rng.gen_bool(0.3)).collect()
+        };
+
+        let key_restrictions = keys.map_nums(|_| {
+            Restriction::Not(random_subset(rng))
+        });
+        let mut constraint_table = ConstraintTable::new(keys.count());
+        for origin in keys.nums() {
+            for target in keys.nums() {
+                let restrictor = values.map_nums(|_| {
+                    if origin == target || !rng.gen_bool(density) {
+                        Restriction::Not(vec![])
+                    } else if rng.gen_bool(0.5) {
+                        Restriction::Not(random_subset(rng))
+                    } else {
+                        Restriction::Only(random_subset(rng))
+                    }
+                });
+                constraint_table.set(origin, target, restrictor);
+            }
+        }
+
+        Domain {
+            keys,
+            values,
+            key_restrictions,
+            constraint_table,
+            all_different: Vec::new(),
+            blank: None,
+            cardinality: Vec::new(),
+        }
+    }
+
+    /// Graph colouring on a random graph.
+    fn random_colouring<R: Rng>(rng: &mut R,
+                                num_keys: usize,
+                                num_colours: usize,
+                                edge_prob: f64)
+                                -> Domain
+    {
+        let values: Table<Value, String> = Table::from_vec(
+            (0..num_colours).map(|v| v.to_string()).collect());
+        let keys: Table<Key, String> = Table::from_vec(
+            (0..num_keys).map(|k| k.to_string()).collect());
+
+        let key_restrictions = keys.map_nums(|_| Restriction::Not(vec![]));
+        let mut constraint_table = ConstraintTable::new(keys.count());
+        for origin in keys.nums() {
+            for target in keys.nums() {
+                if origin < target && rng.gen_bool(edge_prob) {
+                    let restrictor = values.map_nums(|value_num| {
+                        Restriction::Not(vec![value_num])
+                    });
+                    constraint_table.set(origin, target, restrictor);
+                }
+            }
+        }
+
+        Domain {
+            keys,
+            values,
+            key_restrictions,
+            constraint_table,
+            all_different: Vec::new(),
+            blank: None,
+            cardinality: Vec::new(),
+        }
+    }
+
+    fn brute_force_count(domain: &Domain) -> usize {
+        let num_keys = domain.keys.count().as_usize();
+        let num_values = domain.values.count().as_usize();
+        let mut count = 0;
+        for n in 0..num_values.pow(num_keys as u32) {
+            let mapping = domain.keys.map_nums(|key_num| {
+                let digit = n / num_values.pow(key_num.as_usize() as u32);
+                domain.values.nums().nth(digit % num_values).unwrap()
+            });
+            if mapping_valid(&mapping, domain) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Keys a, x, y, z and w with colours 0, 1 and 2. When a is 0, x
+    /// cannot be 2. When x is 0 or 1, the triangle y, z, w has only two
+    /// colours left, which arc consistency does not detect. So when a is 0,
+    /// x runs out of values, but only because of a.
+    fn blamed_rejection_domain() -> Domain {
+        let values: Table<Value, String> = Table::from_vec(
+            (0..3).map(|v| v.to_string()).collect());
+        let keys: Table<Key, String> = Table::from_vec(
+            ["a", "x", "y", "z", "w"].iter().map(|k| k.to_string()).collect());
+        let key = |name| keys.nums().find(|&k| keys[k] == name).unwrap();
+        let value = |v: usize| values.nums().nth(v).unwrap();
+
+        let mut constraint_table = ConstraintTable::new(keys.count());
+        constraint_table.set(key("a"), key("x"), values.map_nums(|value_num| {
+            if value_num == value(0) {
+                Restriction::Not(vec![value(2)])
+            } else {
+                Restriction::Not(vec![])
+            }
+        }));
+        for &target in ["y", "z", "w"].iter() {
+            constraint_table.set(key("x"), key(target), values.map_nums(|value_num| {
+                if value_num == value(0) {
+                    Restriction::Not(vec![value(2)])
+                } else if value_num == value(1) {
+                    Restriction::Not(vec![value(0)])
+                } else {
+                    Restriction::Not(vec![])
+                }
+            }));
+        }
+        for &(origin, target) in [("y", "z"), ("y", "w"), ("z", "w")].iter() {
+            constraint_table.set(key(origin), key(target), values.map_nums(|value_num| {
+                Restriction::Not(vec![value_num])
+            }));
+        }
+
+        Domain {
+            key_restrictions: keys.map_nums(|_| Restriction::Not(vec![])),
+            keys,
+            values,
+            constraint_table,
+            all_different: Vec::new(),
+            blank: None,
+            cardinality: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_backjump_blames_rejections() {
+        // backjumping from x has to go back to a, even though none of the
+        // wipeouts below x involve a
+        let domain = blamed_rejection_domain();
+        let count = brute_force_count(&domain);
+        assert_eq!(count, 12);
+        let mut g = Backtracker::new(&domain);
+        assert_eq!(g.count_solutions(), count);
+    }
+
+    #[test]
+    fn test_count_random() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for _ in 0..200 {
+            let domain = random_domain(&mut rng, 6, 3, 0.3);
+            let mut g = Backtracker::new(&domain);
+            assert_eq!(g.count_solutions(), brute_force_count(&domain));
+        }
+    }
+
+    #[test]
+    fn test_learned_nogoods() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        let mut num_learned = 0;
+        for _ in 0..30 {
+            let domain = random_colouring(&mut rng, 14, 3, 0.35);
+            let mut g = Backtracker::new(&domain);
+            let count = g.count_solutions();
+
+            // learned nogoods may not rule out any solution
+            let nogoods = g.nogoods().to_vec();
+            num_learned += nogoods.len();
+            let mut g = Backtracker::new(&domain);
+            g.add_nogoods(nogoods);
+            assert_eq!(g.count_solutions(), count);
+        }
+        assert!(num_learned > 0);
+    }
+
+    #[test]
+    fn test_restarts() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for seed in 0..100 {
+            let domain = random_domain(&mut rng, 6, 3, 0.3);
+            let mut g = Backtracker::new(&domain);
+            g.set_seed(seed);
+            g.set_restarts(Restarts::Luby { unit: 1 });
+            assert_eq!(g.count_solutions(), brute_force_count(&domain));
+        }
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for seed in 0..30 {
+            let domain = random_colouring(&mut rng, 14, 3, 0.35);
+            let expected = Backtracker::new(&domain).count_solutions();
+            let mut g = Backtracker::new(&domain);
+            g.set_seed(seed);
+            g.set_restarts(Restarts::Luby { unit: 1 });
+
+            // split off what is left every few nodes, and search it apart
+            let mut items = Vec::new();
+            let mut count = 0;
+            loop {
+                let nodes = g.stats().nodes + 3;
+                g.set_limits(Limits { nodes: Some(nodes), ..Limits::none() });
+                match g.generate() {
+                    SearchResult::Solution(_) => count += 1,
+                    SearchResult::Exhausted => break,
+                    SearchResult::LimitReached => items.extend(g.split_off()),
+                }
+            }
+            for item in items {
+                let mut g = Backtracker::new(&domain);
+                g.pin(item);
+                count += g.count_solutions();
+            }
+            assert_eq!(count, expected);
+
+            // the values that were split off were not tried, so nothing may
+            // have been learned from them
+            let nogoods = g.nogoods().to_vec();
+            let mut g = Backtracker::new(&domain);
+            g.add_nogoods(nogoods);
+            assert_eq!(g.count_solutions(), expected);
+        }
+    }
+
+    #[test]
+    fn test_all_different() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for _ in 0..100 {
+            let mut domain = random_domain(&mut rng, 6, 4, 0.15);
+            for _ in 0..2 {
+                let group = domain.keys.nums()
+                    .filter(|_| rng.gen_bool(0.6))
+                    .collect();
+                domain.all_different.push(group);
+            }
+            let count = brute_force_count(&domain);
+
+            let mut g = Backtracker::new(&domain);
+            assert_eq!(g.count_solutions(), count);
+
+            // the explanations of all-different pruning are used for
+            // backjumping and learning, so learned nogoods have to hold
+            let nogoods = g.nogoods().to_vec();
+            let mut g = Backtracker::new(&domain);
+            g.add_nogoods(nogoods);
+            assert_eq!(g.count_solutions(), count);
+        }
+    }
+
+    #[test]
+    fn test_cardinality() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for _ in 0..200 {
+            let mut domain = random_domain(&mut rng, 6, 4, 0.15);
+            let group = domain.keys.nums()
+                .filter(|_| rng.gen_bool(0.8))
+                .collect();
+            domain.all_different.push(group);
+            if rng.gen_bool(0.5) {
+                domain.blank = domain.values.nums().next();
+            }
+            for value_num in domain.values.nums().skip(1) {
+                if rng.gen_bool(0.3) {
+                    continue;
+                }
+                let min = rng.gen_range(0, 3);
+                let max = if rng.gen_bool(0.5) {
+                    Some(min + rng.gen_range(0, 3))
+                } else {
+                    None
+                };
+                domain.cardinality.push(Cardinality { value: value_num, min, max });
+            }
+            let count = brute_force_count(&domain);
+
+            let mut g = Backtracker::new(&domain);
+            let mut found = 0;
+            while let SearchResult::Solution(mapping) = g.generate() {
+                assert!(mapping_valid(&mapping, &domain));
+                found += 1;
+            }
+            assert_eq!(found, count);
+
+            let nogoods = g.nogoods().to_vec();
+            let mut g = Backtracker::new(&domain);
+            g.add_nogoods(nogoods);
+            assert_eq!(g.count_solutions(), count);
+        }
+    }
+
+    #[test]
+    fn test_pins() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for _ in 0..100 {
+            let mut domain = random_domain(&mut rng, 6, 3, 0.3);
+            let key_num = domain.keys.nums().nth(rng.gen_range(0, 6)).unwrap();
+            let value_num = domain.values.nums().nth(rng.gen_range(0, 3)).unwrap();
+            let pin = Assignment { key_num, value_num };
+
+            let mut g = Backtracker::new(&domain);
+            g.pin(vec![pin]);
+            let mut count = 0;
+            while let SearchResult::Solution(mapping) = g.generate() {
+                assert_eq!(mapping[key_num], value_num);
+                // the pinned key is never searched over
+                assert!(g.stack.iter().all(|step| step.key_num != key_num));
+                count += 1;
+            }
+            drop(g);
+
+            // pinning is the same as restricting the key to the value
+            let restriction = Restriction::Only(vec![value_num]);
+            domain.key_restrictions[key_num] =
+                domain.key_restrictions[key_num].intersect(&restriction);
+            assert_eq!(count, brute_force_count(&domain));
+        }
+    }
+
+    #[test]
+    fn test_heuristics() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for _ in 0..50 {
+            let domain = random_domain(&mut rng, 6, 3, 0.3);
+            let count = brute_force_count(&domain);
+
+            let variable_orders = [VariableHeuristic::Mrv,
+                                   VariableHeuristic::Degree,
+                                   VariableHeuristic::DomWdeg];
+            for variable_order in variable_orders.iter() {
+                let mut g = Backtracker::new(&domain);
+                g.set_variable_order(variable_order.build());
+                assert_eq!(g.count_solutions(), count);
+            }
+
+            let mut g = Backtracker::new(&domain);
+            g.set_value_order(Box::new(LeastConstraining));
+            assert_eq!(g.count_solutions(), count);
+        }
+    }
+
+    #[test]
+    fn test_seeded_order() {
+        let domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+
+        let first_solution = |seed| {
+            let mut g = Backtracker::new(&domain);
+            g.set_seed(seed);
+            match g.generate() {
+                SearchResult::Solution(mapping) => mapping,
+                _ => panic!("no solution found"),
+            }
+        };
+
+        // the same seed gives the same solution, but not every seed does
+        assert!(first_solution(1) == first_solution(1));
+        assert!((2..20).any(|seed| first_solution(seed) != first_solution(1)));
+    }
+
+    #[test]
+    fn test_count_petersen() {
+        let domain = json::read_config("petersen.json")
+            .context("Could not parse domain").unwrap();
+
+        // the chromatic polynomial of the petersen graph evaluated at 3
+        let mut g = Backtracker::new(&domain);
+        assert_eq!(g.count_solutions(), 120);
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,308 @@
+use data::*;
+use cat::*;
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use Result;
+
+/// Decides which key gets assigned next.
+pub trait VariableOrder {
+    fn select(&mut self,
+              walker: &DomainWalker,
+              unassigned: &HashSet<Num<Key>>)
+              -> Option<Num<Key>>;
+
+    /// Called when the range of key_num was wiped out by the assignments of
+    /// the keys in conflict.
+    fn on_wipeout(&mut self, _key_num: Num<Key>, _conflict: &[Num<Key>]) {}
+}
+
+/// Decides in which order the values for a key are tried.
+pub trait ValueOrder {
+    /// Put the most promising values first. The sort should be stable, so
+    /// that a random order that was set up beforehand breaks the ties.
+    fn order(&mut self,
+             walker: &DomainWalker,
+             key_num: Num<Key>,
+             values: &mut [Num<Value>]);
+}
+
+/// Minimum remaining values: pick the key with the smallest range, so that
+/// failures show up early.
+pub struct Mrv;
+
+impl VariableOrder for Mrv {
+    fn select(&mut self,
+              walker: &DomainWalker,
+              unassigned: &HashSet<Num<Key>>)
+              -> Option<Num<Key>>
+    {
+        unassigned.iter().cloned().min_by_key(|&key_num| {
+            (walker.range_for(key_num).len(), key_num)
+        })
+    }
+}
+
+/// Pick the key that constrains the most unassigned keys, breaking ties by
+/// range size.
+pub struct Degree;
+
+impl VariableOrder for Degree {
+    fn select(&mut self,
+              walker: &DomainWalker,
+              unassigned: &HashSet<Num<Key>>)
+              -> Option<Num<Key>>
+    {
+        unassigned.iter().cloned().min_by_key(|&key_num| {
+            let degree = unassigned_neighbours(walker, key_num).count();
+            (Reverse(degree), walker.range_for(key_num).len(), key_num)
+        })
+    }
+}
+
+/// Range size divided by weighted degree. Every constraint starts out with
+/// weight one, and gains weight each time it takes part in a wipeout, so
+/// that the search focuses on the hard parts of the domain.
+pub struct DomWdeg {
+    // weights of the key pairs that took part in a wipeout, smallest key
+    // first.
+    weights: HashMap<(Num<Key>, Num<Key>), usize>,
+}
+
+impl DomWdeg {
+    pub fn new() -> Self {
+        DomWdeg { weights: HashMap::new() }
+    }
+
+    fn weight(&self, a: Num<Key>, b: Num<Key>) -> usize {
+        let pair = if a < b { (a, b) } else { (b, a) };
+        self.weights.get(&pair).cloned().unwrap_or(1)
+    }
+
+    fn wdeg(&self, walker: &DomainWalker, key_num: Num<Key>) -> usize {
+        unassigned_neighbours(walker, key_num)
+            .map(|other| self.weight(key_num, other))
+            .sum()
+    }
+}
+
+impl Default for DomWdeg {
+    fn default() -> Self {
+        DomWdeg::new()
+    }
+}
+
+impl VariableOrder for DomWdeg {
+    fn select(&mut self,
+              walker: &DomainWalker,
+              unassigned: &HashSet<Num<Key>>)
+              -> Option<Num<Key>>
+    {
+        let scored = unassigned.iter().map(|&key_num| {
+            (key_num, walker.range_for(key_num).len(), self.wdeg(walker, key_num))
+        });
+        let best = scored.min_by(|&(a, a_dom, a_wdeg), &(b, b_dom, b_wdeg)| {
+            // keys without unassigned neighbours come last; the others are
+            // compared by dom/wdeg without dividing.
+            (a_wdeg == 0).cmp(&(b_wdeg == 0))
+                .then((a_dom * b_wdeg).cmp(&(b_dom * a_wdeg)))
+                .then(a.cmp(&b))
+        });
+        best.map(|(key_num, _, _)| key_num)
+    }
+
+    fn on_wipeout(&mut self, key_num: Num<Key>, conflict: &[Num<Key>]) {
+        for &other in conflict.iter() {
+            if other == key_num {
+                continue;
+            }
+            let pair = if key_num < other { (key_num, other) } else { (other, key_num) };
+            *self.weights.entry(pair).or_insert(1) += 1;
+        }
+    }
+}
+
+fn unassigned_neighbours<'a>(walker: &'a DomainWalker, key_num: Num<Key>)
+    -> impl Iterator<Item = Num<Key>> + 'a
+{
+    walker.neighbours(key_num).iter().cloned().filter(move |&other| {
+        walker.mapping()[other].is_none()
+    })
+}
+
+/// Try values in the order of their range.
+pub struct RangeOrder;
+
+impl ValueOrder for RangeOrder {
+    fn order(&mut self, _: &DomainWalker, _: Num<Key>, _: &mut [Num<Value>]) {}
+}
+
+/// Try the values that rule out the fewest values for the unassigned
+/// neighbours first.
+pub struct LeastConstraining;
+
+impl ValueOrder for LeastConstraining {
+    fn order(&mut self,
+             walker: &DomainWalker,
+             key_num: Num<Key>,
+             values: &mut [Num<Value>])
+    {
+        values.sort_by_cached_key(|&value_num| {
+            let a = Assignment { key_num, value_num };
+            let mut num_ruled_out = 0;
+            for other in unassigned_neighbours(walker, key_num) {
+                num_ruled_out += walker.range_for(other).len() -
+                    walker.num_supports(a, other);
+            }
+            num_ruled_out
+        });
+    }
+}
+
+/// Try the values with the highest score first. Unscored values count as
+/// zero.
+pub struct ScoreGuided {
+    scores: Table<Key, Table<Value, f64>>,
+}
+
+impl ScoreGuided {
+    pub fn new(scores: Table<Key, Table<Value, f64>>) -> Self {
+        ScoreGuided { scores }
+    }
+}
+
+impl ValueOrder for ScoreGuided {
+    fn order(&mut self,
+             _: &DomainWalker,
+             key_num: Num<Key>,
+             values: &mut [Num<Value>])
+    {
+        let scores = &self.scores[key_num];
+        values.sort_by(|&a, &b| {
+            scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal)
+        });
+    }
+}
+
+/// Variable ordering heuristics that can be selected by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableHeuristic {
+    Mrv,
+    Degree,
+    DomWdeg,
+}
+
+impl VariableHeuristic {
+    pub fn build(&self) -> Box<dyn VariableOrder> {
+        match *self {
+            VariableHeuristic::Mrv => Box::new(Mrv),
+            VariableHeuristic::Degree => Box::new(Degree),
+            VariableHeuristic::DomWdeg => Box::new(DomWdeg::new()),
+        }
+    }
+}
+
+impl FromStr for VariableHeuristic {
+    type Err = ::failure::Error;
+
+    fn from_str(s: &str) -> Result<VariableHeuristic> {
+        Ok(match s {
+            "mrv" => VariableHeuristic::Mrv,
+            "degree" => VariableHeuristic::Degree,
+            "dom-wdeg" => VariableHeuristic::DomWdeg,
+            _ => bail!("unknown variable order: {}", s),
+        })
+    }
+}
+
+/// Value ordering heuristics that can be selected by name. Scores are read
+/// from the given file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueHeuristic {
+    Range,
+    LeastConstraining,
+    Scores(String),
+}
+
+impl FromStr for ValueHeuristic {
+    type Err = ::failure::Error;
+
+    /// Parses `range`, `lcv` or `scores:<path>`.
+    fn from_str(s: &str) -> Result<ValueHeuristic> {
+        if let Some(path) = s.strip_prefix("scores:") {
+            return Ok(ValueHeuristic::Scores(path.to_string()));
+        }
+        Ok(match s {
+            "range" => ValueHeuristic::Range,
+            "lcv" => ValueHeuristic::LeastConstraining,
+            _ => bail!("unknown value order: {}", s),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use failure::ResultExt;
+    use json;
+
+    fn key(domain: &Domain, name: &str) -> Num<Key> {
+        domain.keys.enumerate().find(|&(_, n)| n == name).unwrap().0
+    }
+
+    fn value(domain: &Domain, name: &str) -> Num<Value> {
+        domain.values.enumerate().find(|&(_, n)| n == name).unwrap().0
+    }
+
+    #[test]
+    fn test_variable_orders() {
+        let domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+        let walker = DomainWalker::new(&domain);
+        let unassigned = domain.keys.nums().collect();
+
+        // the lowercase keys have the smallest ranges
+        assert_eq!(Mrv.select(&walker, &unassigned), Some(key(&domain, "1/lower")));
+        // every lowercase key constrains three others
+        assert_eq!(Degree.select(&walker, &unassigned), Some(key(&domain, "1/lower")));
+
+        // 2/lower gains the most weight from taking part in a wipeout
+        let mut dom_wdeg = DomWdeg::new();
+        let conflict = [key(&domain, "1/lower"), key(&domain, "3/lower")];
+        dom_wdeg.on_wipeout(key(&domain, "2/lower"), &conflict);
+        assert_eq!(dom_wdeg.select(&walker, &unassigned), Some(key(&domain, "2/lower")));
+    }
+
+    #[test]
+    fn test_value_orders() {
+        let domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+        let mut walker = DomainWalker::new(&domain);
+        walker.assign(key(&domain, "1/lower"), value(&domain, "a"));
+        walker.propagate_from(key(&domain, "1/lower"));
+
+        // for 2/lower, b and c both rule out one value of 3/lower and one of
+        // 2/upper; the order is kept
+        let two = key(&domain, "2/lower");
+        let mut values = walker.range_for(two).to_vec();
+        LeastConstraining.order(&walker, two, &mut values);
+        assert_eq!(values, [value(&domain, "b"), value(&domain, "c")]);
+
+        let mut scores = domain.keys.map_nums(|_| domain.values.map_nums(|_| 0.0));
+        scores[two][value(&domain, "c")] = 1.0;
+        ScoreGuided::new(scores).order(&walker, two, &mut values);
+        assert_eq!(values, [value(&domain, "c"), value(&domain, "b")]);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("dom-wdeg".parse::<VariableHeuristic>().unwrap(),
+                   VariableHeuristic::DomWdeg);
+        assert!("random".parse::<VariableHeuristic>().is_err());
+        assert_eq!("scores:s.json".parse::<ValueHeuristic>().unwrap(),
+                   ValueHeuristic::Scores("s.json".to_string()));
+        assert!("best".parse::<ValueHeuristic>().is_err());
+    }
+}
@@ -1,3 +1,15 @@
 mod generator;
+mod explain;
+mod heuristics;
+mod restarts;
+mod observer;
+mod stats;
+mod parallel;
 
-pub use self::generator::Backtracker;
\ No newline at end of file
+pub use self::generator::{Backtracker, SearchResult};
+pub use self::explain::explain;
+pub use self::heuristics::*;
+pub use self::restarts::Restarts;
+pub use self::observer::{Event, SearchObserver, Trace};
+pub use self::stats::{Limits, SearchStats};
+pub use self::parallel::{ParallelResult, ParallelSolver};
@@ -0,0 +1,148 @@
+use data::*;
+use cat::*;
+
+use std::io::{self, Write};
+
+/// Something that happened during a search.
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    /// A key was assigned a value, either by the search or by pinning.
+    Assign(Assignment),
+    /// An assignment was undone.
+    Unassign(Assignment),
+    /// The range of a key was wiped out by the assignments of the
+    /// conflicting keys.
+    Wipeout {
+        key_num: Num<Key>,
+        conflict: &'a [Num<Key>],
+    },
+    /// The search jumped back to the step at given depth, to try its next
+    /// value.
+    Backtrack { depth: usize },
+    /// The search started over from the root.
+    Restart,
+    /// A solution was found.
+    Solution(&'a Table<Key, Num<Value>>),
+}
+
+impl<'a> Event<'a> {
+    /// The verbosity from which an event gets traced: solutions and
+    /// restarts at 1, wipeouts and backtracks at 2, assignments at 3.
+    pub fn level(&self) -> usize {
+        match self {
+            Event::Solution(_) | Event::Restart => 1,
+            Event::Wipeout { .. } | Event::Backtrack { .. } => 2,
+            Event::Assign(_) | Event::Unassign(_) => 3,
+        }
+    }
+}
+
+/// Gets notified of the events of a search, to trace or inspect it.
+pub trait SearchObserver {
+    fn notify(&mut self, domain: &Domain, event: &Event);
+}
+
+/// Ignores all events.
+pub struct Silent;
+
+impl SearchObserver for Silent {
+    fn notify(&mut self, _domain: &Domain, _event: &Event) {}
+}
+
+/// Writes the events up to a verbosity level in a readable form.
+pub struct Trace<W> {
+    out: W,
+    verbosity: usize,
+}
+
+impl Trace<io::Stderr> {
+    /// Trace to stderr, so that the trace does not mix with the results.
+    pub fn to_stderr(verbosity: usize) -> Self {
+        Trace::new(io::stderr(), verbosity)
+    }
+}
+
+impl<W: Write> Trace<W> {
+    pub fn new(out: W, verbosity: usize) -> Self {
+        Trace { out, verbosity }
+    }
+
+    fn write(&mut self, domain: &Domain, event: &Event) -> io::Result<()> {
+        match *event {
+            Event::Assign(a) => writeln!(
+                self.out, "assign {} = {}",
+                domain.keys[a.key_num], domain.values[a.value_num]
+            ),
+            Event::Unassign(a) => writeln!(
+                self.out, "unassign {} = {}",
+                domain.keys[a.key_num], domain.values[a.value_num]
+            ),
+            Event::Wipeout { key_num, conflict } => {
+                let names: Vec<&str> = conflict.iter()
+                    .map(|&key_num| domain.keys[key_num].as_str())
+                    .collect();
+                writeln!(self.out, "wipeout of {}, caused by [{}]",
+                         domain.keys[key_num], names.join(", "))
+            }
+            Event::Backtrack { depth } => {
+                writeln!(self.out, "backtrack to depth {}", depth)
+            }
+            Event::Restart => writeln!(self.out, "restart"),
+            Event::Solution(mapping) => {
+                let assignments: Vec<String> = mapping.enumerate()
+                    .map(|(key_num, &value_num)| {
+                        format!("{} = {}", domain.keys[key_num], domain.values[value_num])
+                    })
+                    .collect();
+                writeln!(self.out, "solution: {}", assignments.join(", "))
+            }
+        }
+    }
+}
+
+impl<W: Write> SearchObserver for Trace<W> {
+    fn notify(&mut self, domain: &Domain, event: &Event) {
+        if event.level() > self.verbosity {
+            return;
+        }
+        // a trace that cannot be written should not stop the search
+        let _ = self.write(domain, event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use algorithm::{Backtracker, SearchResult};
+    use failure::ResultExt;
+    use json;
+
+    #[test]
+    fn test_trace() {
+        let domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+
+        let mut out = Vec::new();
+        {
+            let mut g = Backtracker::new(&domain);
+            g.set_observer(Box::new(Trace::new(&mut out, 3)));
+            match g.generate() {
+                SearchResult::Solution(_) => (),
+                _ => panic!("no solution found"),
+            }
+        }
+        let trace = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert!(lines[0].starts_with("assign "));
+        assert!(lines.last().unwrap().starts_with("solution: "));
+
+        let mut out = Vec::new();
+        {
+            let mut g = Backtracker::new(&domain);
+            g.set_observer(Box::new(Trace::new(&mut out, 1)));
+            assert_eq!(g.count_solutions(), 6);
+        }
+        let trace = String::from_utf8(out).unwrap();
+        assert_eq!(trace.lines().count(), 6);
+    }
+}
@@ -0,0 +1,384 @@
+use data::*;
+use cat::*;
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::generator::{Backtracker, SearchResult};
+use super::stats::{Limits, SearchStats};
+
+/// A busy worker checks whether others are waiting for work every this many
+/// nodes.
+const SPLIT_INTERVAL: usize = 100;
+
+/// How long a worker without work waits before looking again.
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+/// Searches a domain on several threads. The search starts as a single
+/// subtree, given by the pins. Every worker searches its subtrees with its
+/// own `Backtracker`; while other workers are waiting for work, a busy
+/// worker splits off the rest of its subtree nearest to the root, leaving
+/// the new subtrees in its queue for the others to steal.
+pub struct ParallelSolver<'d> {
+    domain: &'d Domain,
+    num_threads: usize,
+    pins: Vec<Assignment>,
+    time_limit: Option<Duration>,
+    // applied to the backtracker of every work item
+    configure: Box<dyn Fn(&mut Backtracker) + Sync + 'd>,
+    stats: SearchStats,
+    num_nogoods: usize,
+}
+
+/// How a parallel search ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParallelResult {
+    /// All subtrees were searched.
+    Exhausted,
+    /// The solution callback asked to stop.
+    Stopped,
+    /// The time limit ran out.
+    LimitReached,
+}
+
+type WorkItem = Vec<Assignment>;
+
+impl<'d> ParallelSolver<'d> {
+    pub fn new(domain: &'d Domain, num_threads: usize) -> Self {
+        assert!(num_threads > 0, "at least one thread is needed");
+        ParallelSolver {
+            domain,
+            num_threads,
+            pins: Vec::new(),
+            time_limit: None,
+            configure: Box::new(|_| ()),
+            stats: SearchStats::default(),
+            num_nogoods: 0,
+        }
+    }
+
+    /// Fix the given assignments in every subtree, like `Backtracker::pin`.
+    pub fn pin<I>(&mut self, pins: I)
+        where I: IntoIterator<Item = Assignment>
+    {
+        self.pins.extend(pins);
+    }
+
+    /// Stop the search once it has run for this long.
+    pub fn set_time_limit(&mut self, time_limit: Option<Duration>) {
+        self.time_limit = time_limit;
+    }
+
+    /// Set up the backtracker of every work item, e.g. its heuristics. The
+    /// limits of the backtrackers are not used for the search as a whole.
+    pub fn set_configure(&mut self, configure: Box<dyn Fn(&mut Backtracker) + Sync + 'd>) {
+        self.configure = configure;
+    }
+
+    /// The work done by the workers together, over the wall-clock time of
+    /// the last run.
+    pub fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    /// The number of nogoods the workers learned together.
+    pub fn num_nogoods(&self) -> usize {
+        self.num_nogoods
+    }
+
+    /// Search all subtrees, calling on_solution on the calling thread for
+    /// every solution found, until it returns false. Solutions arrive in no
+    /// particular order.
+    pub fn run<F>(&mut self, mut on_solution: F) -> ParallelResult
+        where F: FnMut(Table<Key, Num<Value>>) -> bool
+    {
+        let start = Instant::now();
+        let deadline = self.time_limit.map(|limit| start + limit);
+
+        let pool = Pool::new(self.num_threads);
+        pool.push(0, self.pins.clone());
+        let stop = AtomicBool::new(false);
+
+        let mut result = ParallelResult::Exhausted;
+        let mut stats = SearchStats::default();
+        let mut num_nogoods = 0;
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            let workers: Vec<_> = (0..self.num_threads).map(|worker| {
+                let sender = sender.clone();
+                let (this, pool, stop) = (&*self, &pool, &stop);
+                scope.spawn(move || this.work(worker, pool, stop, sender))
+            }).collect();
+            // the channel closes once every worker is done
+            drop(sender);
+
+            loop {
+                let received = match deadline {
+                    Some(deadline) => {
+                        let timeout = deadline.saturating_duration_since(Instant::now());
+                        receiver.recv_timeout(timeout)
+                    }
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match received {
+                    Ok(mapping) => {
+                        if !on_solution(mapping) {
+                            result = ParallelResult::Stopped;
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        result = ParallelResult::LimitReached;
+                        break;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            stop.store(true, Ordering::Relaxed);
+
+            for worker in workers {
+                let (worker_stats, worker_nogoods) = worker.join().unwrap();
+                stats.merge(&worker_stats);
+                num_nogoods += worker_nogoods;
+            }
+        });
+
+        // the workers ran at the same time, so their times do not add up
+        stats.elapsed = start.elapsed();
+        self.stats = stats;
+        self.num_nogoods = num_nogoods;
+        result
+    }
+
+    /// Find any solution.
+    pub fn first_solution(&mut self) -> SearchResult {
+        let mut found = None;
+        let result = self.run(|mapping| {
+            found = Some(mapping);
+            false
+        });
+        match (result, found) {
+            (_, Some(mapping)) => SearchResult::Solution(mapping),
+            (ParallelResult::LimitReached, None) => SearchResult::LimitReached,
+            (_, None) => SearchResult::Exhausted,
+        }
+    }
+
+    /// Count all solutions.
+    pub fn count_solutions(&mut self) -> usize {
+        let mut count = 0;
+        self.run(|_| {
+            count += 1;
+            true
+        });
+        count
+    }
+
+    /// Search the work items, taking them from the worker's own queue
+    /// first. Returns the stats and the number of nogoods of the searches.
+    fn work(&self,
+            worker: usize,
+            pool: &Pool,
+            stop: &AtomicBool,
+            sender: Sender<Table<Key, Num<Value>>>)
+            -> (SearchStats, usize)
+    {
+        let mut stats = SearchStats::default();
+        let mut num_nogoods = 0;
+        while let Some(item) = pool.take(worker, stop) {
+            let depth = item.len() - self.pins.len();
+
+            let mut b = Backtracker::new(self.domain);
+            (self.configure)(&mut b);
+            b.set_stop_flag(stop);
+            b.pin(item.clone());
+            loop {
+                // search in slices, to split off work in between
+                let nodes = b.stats().nodes + SPLIT_INTERVAL;
+                b.set_limits(Limits { nodes: Some(nodes), ..Limits::none() });
+                match b.generate() {
+                    SearchResult::Solution(mapping) => {
+                        if sender.send(mapping).is_err() {
+                            break;
+                        }
+                    }
+                    SearchResult::Exhausted => break,
+                    SearchResult::LimitReached => {
+                        if stop.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if pool.is_starved() {
+                            for path in b.split_off() {
+                                pool.push(worker, [item.as_slice(), &path].concat());
+                            }
+                        }
+                    }
+                }
+            }
+
+            // the assignments of the item were split off the same tree
+            let mut item_stats = b.stats().clone();
+            item_stats.max_depth += depth;
+            stats.merge(&item_stats);
+            num_nogoods += b.nogoods().len();
+        }
+        (stats, num_nogoods)
+    }
+}
+
+/// The work items waiting to be searched, in a queue per worker.
+struct Pool {
+    queues: Vec<Mutex<VecDeque<WorkItem>>>,
+    // the workers that are searching, or about to take an item
+    num_busy: AtomicUsize,
+}
+
+impl Pool {
+    fn new(num_workers: usize) -> Self {
+        Pool {
+            queues: (0..num_workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            num_busy: AtomicUsize::new(num_workers),
+        }
+    }
+
+    fn push(&self, worker: usize, item: WorkItem) {
+        self.queues[worker].lock().unwrap().push_back(item);
+    }
+
+    /// Whether some worker is waiting while no items are left to take.
+    fn is_starved(&self) -> bool {
+        self.num_busy.load(Ordering::SeqCst) < self.queues.len() &&
+            self.queues.iter().all(|queue| queue.lock().unwrap().is_empty())
+    }
+
+    /// Take the next item from the worker's own queue, or steal the last
+    /// item of another worker when that runs dry. When there is none, wait
+    /// for the busy workers to split off more. Returns None once no worker
+    /// is busy any more, or the search is stopped.
+    fn take(&self, worker: usize, stop: &AtomicBool) -> Option<WorkItem> {
+        loop {
+            if let Some(item) = self.find(worker) {
+                return Some(item);
+            }
+            self.num_busy.fetch_sub(1, Ordering::SeqCst);
+            loop {
+                if stop.load(Ordering::Relaxed) ||
+                    self.num_busy.load(Ordering::SeqCst) == 0
+                {
+                    return None;
+                }
+                if self.queues.iter().any(|queue| !queue.lock().unwrap().is_empty()) {
+                    // busy before taking, so that the others keep waiting
+                    self.num_busy.fetch_add(1, Ordering::SeqCst);
+                    break;
+                }
+                thread::sleep(IDLE_WAIT);
+            }
+        }
+    }
+
+    fn find(&self, worker: usize) -> Option<WorkItem> {
+        if let Some(item) = self.queues[worker].lock().unwrap().pop_front() {
+            return Some(item);
+        }
+        for offset in 1..self.queues.len() {
+            let victim = (worker + offset) % self.queues.len();
+            if let Some(item) = self.queues[victim].lock().unwrap().pop_back() {
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::heuristics::VariableHeuristic;
+    use super::super::restarts::Restarts;
+    use failure::ResultExt;
+    use json;
+
+    #[test]
+    fn test_count() {
+        let domain = json::read_config("petersen.json")
+            .context("Could not parse domain").unwrap();
+        for &num_threads in [1, 2, 4].iter() {
+            let mut solver = ParallelSolver::new(&domain, num_threads);
+            assert_eq!(solver.count_solutions(), 120);
+        }
+
+        let domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+        let mut solver = ParallelSolver::new(&domain, 4);
+        let mut solutions = Vec::new();
+        solver.run(|mapping| {
+            solutions.push(mapping);
+            true
+        });
+        assert_eq!(solutions.len(), 6);
+        for (idx, mapping) in solutions.iter().enumerate() {
+            assert!(!solutions[..idx].contains(mapping));
+        }
+        assert_eq!(solver.stats().max_depth, 6);
+    }
+
+    #[test]
+    fn test_configure() {
+        // the workers split their trees the way they search them
+        let domain = json::read_config("petersen.json")
+            .context("Could not parse domain").unwrap();
+        let mut solver = ParallelSolver::new(&domain, 4);
+        solver.set_configure(Box::new(|b| {
+            b.set_seed(7);
+            b.set_restarts(Restarts::Luby { unit: 1 });
+            b.set_variable_order(VariableHeuristic::DomWdeg.build());
+        }));
+        assert_eq!(solver.count_solutions(), 120);
+    }
+
+    #[test]
+    fn test_first_solution() {
+        let domain = json::read_config("sudoku.json")
+            .context("Could not parse domain").unwrap();
+        let expected = match Backtracker::new(&domain).generate() {
+            SearchResult::Solution(mapping) => mapping,
+            _ => panic!("no solution found"),
+        };
+
+        let mut solver = ParallelSolver::new(&domain, 4);
+        solver.set_configure(Box::new(|b| b.set_seed(42)));
+        match solver.first_solution() {
+            SearchResult::Solution(mapping) => assert!(mapping == expected),
+            _ => panic!("no solution found"),
+        }
+    }
+
+    #[test]
+    fn test_pins() {
+        let domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+        let find_key = |name: &str| {
+            domain.keys.enumerate().find(|&(_, n)| n == name).unwrap().0
+        };
+        let a = domain.values.enumerate().find(|&(_, n)| n == "a").unwrap().0;
+        let pin = Assignment { key_num: find_key("1/lower"), value_num: a };
+
+        let mut solver = ParallelSolver::new(&domain, 3);
+        solver.pin(vec![pin]);
+        assert_eq!(solver.count_solutions(), 2);
+
+        // pinning an uppercase value on a lowercase key rules out everything
+        let upper = Assignment { key_num: find_key("1/upper"), value_num: a };
+        let mut solver = ParallelSolver::new(&domain, 3);
+        solver.pin(vec![upper]);
+        match solver.first_solution() {
+            SearchResult::Exhausted => (),
+            _ => panic!("expected no solution"),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use Result;
+
+/// When to abandon the current search tree and start over from the root.
+/// The cutoffs are expressed in backtracks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Restarts {
+    Never,
+    /// Cutoffs follow the Luby sequence 1, 1, 2, 1, 1, 2, 4, ... times unit.
+    Luby { unit: usize },
+    /// Cutoffs grow by a constant factor, starting at base.
+    Geometric { base: usize, factor: f64 },
+}
+
+impl Restarts {
+    /// Number of backtracks allowed before the given restart, counting from
+    /// zero. None when the search should never be restarted.
+    pub fn cutoff(&self, restart_num: usize) -> Option<usize> {
+        match *self {
+            Restarts::Never => None,
+            Restarts::Luby { unit } => Some(unit * luby(restart_num + 1)),
+            Restarts::Geometric { base, factor } => {
+                let cutoff = base as f64 * factor.powi(restart_num as i32);
+                Some(cutoff.min(usize::MAX as f64) as usize)
+            }
+        }
+    }
+}
+
+/// The i-th element of the Luby sequence, counting from one.
+fn luby(mut i: usize) -> usize {
+    loop {
+        // find k such that 2^(k-1) <= i < 2^k
+        let mut k = 1;
+        while (1 << k) - 1 < i {
+            k += 1;
+        }
+
+        if i == (1 << k) - 1 {
+            return 1 << (k - 1);
+        }
+        i -= (1 << (k - 1)) - 1;
+    }
+}
+
+impl FromStr for Restarts {
+    type Err = ::failure::Error;
+
+    /// Parses `none`, `luby[:unit]` or `geometric[:base[:factor]]`.
+    fn from_str(s: &str) -> Result<Restarts> {
+        let mut parts = s.split(':');
+        let restarts = match parts.next() {
+            Some("none") => Restarts::Never,
+            Some("luby") => Restarts::Luby {
+                unit: parse_or(parts.next(), 100)?,
+            },
+            Some("geometric") => Restarts::Geometric {
+                base: parse_or(parts.next(), 100)?,
+                factor: parse_or(parts.next(), 1.5)?,
+            },
+            _ => bail!("unknown restart strategy: {}", s),
+        };
+
+        if parts.next().is_some() {
+            bail!("too many parameters for restart strategy: {}", s);
+        }
+
+        // Cutoffs that are zero or never grow make the search restart
+        // forever on an unsatisfiable domain.
+        match restarts {
+            Restarts::Luby { unit: 0 } => {
+                bail!("the luby unit must be at least 1: {}", s);
+            }
+            Restarts::Geometric { base: 0, .. } => {
+                bail!("the geometric base must be at least 1: {}", s);
+            }
+            Restarts::Geometric { factor, .. } if factor.is_nan() || factor <= 1.0 => {
+                bail!("the geometric factor must be greater than 1: {}", s);
+            }
+            _ => (),
+        }
+        Ok(restarts)
+    }
+}
+
+fn parse_or<T: FromStr>(part: Option<&str>, default: T) -> Result<T> {
+    match part {
+        None => Ok(default),
+        Some(part) => match part.parse() {
+            Ok(value) => Ok(value),
+            Err(_) => bail!("invalid restart parameter: {}", part),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_luby() {
+        let seq: Vec<usize> = (1..16).map(luby).collect();
+        assert_eq!(seq, [1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("none".parse::<Restarts>().unwrap(), Restarts::Never);
+        assert_eq!("luby".parse::<Restarts>().unwrap(),
+                   Restarts::Luby { unit: 100 });
+        assert_eq!("geometric:10:2".parse::<Restarts>().unwrap(),
+                   Restarts::Geometric { base: 10, factor: 2.0 });
+        assert!("luby:x".parse::<Restarts>().is_err());
+        assert!("luby:1:2".parse::<Restarts>().is_err());
+        assert!("sometimes".parse::<Restarts>().is_err());
+    }
+
+    #[test]
+    fn test_parse_degenerate() {
+        assert!("luby:0".parse::<Restarts>().is_err());
+        assert!("geometric:0".parse::<Restarts>().is_err());
+        assert!("geometric:10:1".parse::<Restarts>().is_err());
+        assert!("geometric:10:0.5".parse::<Restarts>().is_err());
+        assert!("geometric:10:NaN".parse::<Restarts>().is_err());
+        assert_eq!("luby:1".parse::<Restarts>().unwrap(),
+                   Restarts::Luby { unit: 1 });
+        assert_eq!("geometric:1:1.1".parse::<Restarts>().unwrap(),
+                   Restarts::Geometric { base: 1, factor: 1.1 });
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let luby = Restarts::Luby { unit: 10 };
+        assert_eq!(luby.cutoff(2), Some(20));
+        let geometric = Restarts::Geometric { base: 10, factor: 2.0 };
+        assert_eq!(geometric.cutoff(3), Some(80));
+        assert_eq!(Restarts::Never.cutoff(0), None);
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+/// Counts of the work a search did so far, over all calls to `generate`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchStats {
+    /// Values tried by the search; pinned keys are not counted.
+    pub nodes: usize,
+    /// Jumps back to an earlier key after a wipeout.
+    pub backtracks: usize,
+    pub wipeouts: usize,
+    pub restarts: usize,
+    /// The largest number of keys assigned by the search at once.
+    pub max_depth: usize,
+    /// Time spent searching, not counting the time between calls.
+    pub elapsed: Duration,
+}
+
+impl SearchStats {
+    /// Add the work of another search, e.g. one that covered another part
+    /// of the same tree.
+    pub fn merge(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.backtracks += other.backtracks;
+        self.wipeouts += other.wipeouts;
+        self.restarts += other.restarts;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.elapsed += other.elapsed;
+    }
+}
+
+/// Bounds on the work a search may do before it gives up. Limits are
+/// compared with the totals in `SearchStats`, so they cover all calls to
+/// `generate` together.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub nodes: Option<usize>,
+    pub backtracks: Option<usize>,
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    pub fn none() -> Self {
+        Limits::default()
+    }
+
+    /// Whether the search has done as much work as it may, given the time
+    /// spent in the current call on top of the stats.
+    pub fn reached(&self, stats: &SearchStats, running: Duration) -> bool {
+        if self.nodes.is_some_and(|max| stats.nodes >= max) {
+            return true;
+        }
+        if self.backtracks.is_some_and(|max| stats.backtracks >= max) {
+            return true;
+        }
+        self.time.is_some_and(|max| stats.elapsed + running >= max)
+    }
+}
@@ -0,0 +1,221 @@
+use data::*;
+use cat::*;
+use cat::internal::to_num;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+use Result;
+
+/// Writes the parts of a domain in a fixed order. Numbers are little-endian;
+/// names and lists are prefixed with their length.
+pub struct Encoder<W> {
+    out: W,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(out: W) -> Self {
+        Encoder { out }
+    }
+
+    pub fn domain(&mut self, domain: &Domain) -> Result<()> {
+        self.names(&domain.keys)?;
+        self.names(&domain.values)?;
+
+        for (_, restriction) in domain.key_restrictions.enumerate() {
+            self.restriction(restriction)?;
+        }
+
+        self.len(domain.constraint_table.len())?;
+        for (origin, target, restrictor) in domain.constraint_table.iter() {
+            self.num(origin)?;
+            self.num(target)?;
+            for (_, restriction) in restrictor.enumerate() {
+                self.restriction(restriction)?;
+            }
+        }
+
+        self.len(domain.all_different.len())?;
+        for group in domain.all_different.iter() {
+            self.nums(group)?;
+        }
+
+        match domain.blank {
+            Some(value_num) => {
+                self.out.write_u8(1)?;
+                self.num(value_num)?;
+            }
+            None => self.out.write_u8(0)?,
+        }
+
+        self.len(domain.cardinality.len())?;
+        for c in domain.cardinality.iter() {
+            self.num(c.value)?;
+            self.out.write_u64::<LittleEndian>(c.min as u64)?;
+            match c.max {
+                Some(max) => {
+                    self.out.write_u8(1)?;
+                    self.out.write_u64::<LittleEndian>(max as u64)?;
+                }
+                None => self.out.write_u8(0)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn names<D>(&mut self, names: &Table<D, String>) -> Result<()> {
+        self.len(names.count().as_usize())?;
+        for (_, name) in names.enumerate() {
+            self.len(name.len())?;
+            self.out.write_all(name.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn restriction(&mut self, restriction: &Restriction) -> Result<()> {
+        let (kind, values) = match restriction {
+            Restriction::Not(values) => (0, values),
+            Restriction::Only(values) => (1, values),
+        };
+        self.out.write_u8(kind)?;
+        self.nums(values)
+    }
+
+    fn nums<D>(&mut self, nums: &[Num<D>]) -> Result<()> {
+        self.len(nums.len())?;
+        for &num in nums.iter() {
+            self.num(num)?;
+        }
+        Ok(())
+    }
+
+    fn num<D>(&mut self, num: Num<D>) -> Result<()> {
+        self.out.write_u32::<LittleEndian>(num.as_usize() as u32)?;
+        Ok(())
+    }
+
+    fn len(&mut self, len: usize) -> Result<()> {
+        if len > u32::MAX as usize {
+            bail!("too many elements to compile: {}", len);
+        }
+        self.out.write_u32::<LittleEndian>(len as u32)?;
+        Ok(())
+    }
+}
+
+/// Reads a domain written by `Encoder`, checking that every key and value
+/// number is in range, so that a damaged file gives an error instead of a
+/// domain that crashes the search.
+pub struct Decoder<R> {
+    input: R,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(input: R) -> Self {
+        Decoder { input }
+    }
+
+    pub fn domain(&mut self) -> Result<Domain> {
+        let keys: Table<Key, String> = self.names()?;
+        let values: Table<Value, String> = self.names()?;
+        let num_keys = keys.count().as_usize();
+        let num_values = values.count().as_usize();
+
+        let key_restrictions = self.restrictor(num_keys, num_values)?;
+
+        let mut constraint_table = ConstraintTable::new(keys.count());
+        for _ in 0..self.len()? {
+            let origin: Num<Key> = self.num(num_keys)?;
+            let target: Num<Key> = self.num(num_keys)?;
+            let restrictor = self.restrictor(num_values, num_values)?;
+            constraint_table.set(origin, target, restrictor);
+        }
+
+        let mut all_different = Vec::new();
+        for _ in 0..self.len()? {
+            all_different.push(self.nums(num_keys)?);
+        }
+
+        let blank = match self.input.read_u8()? {
+            0 => None,
+            _ => Some(self.num(num_values)?),
+        };
+
+        let mut cardinality = Vec::new();
+        for _ in 0..self.len()? {
+            let value = self.num(num_values)?;
+            let min = self.input.read_u64::<LittleEndian>()? as usize;
+            let max = match self.input.read_u8()? {
+                0 => None,
+                _ => Some(self.input.read_u64::<LittleEndian>()? as usize),
+            };
+            cardinality.push(Cardinality { value, min, max });
+        }
+
+        Ok(Domain {
+            keys,
+            values,
+            key_restrictions,
+            constraint_table,
+            all_different,
+            blank,
+            cardinality,
+        })
+    }
+
+    fn names<D>(&mut self) -> Result<Table<D, String>> {
+        let mut names = Vec::new();
+        for _ in 0..self.len()? {
+            // a damaged length should not cause a huge allocation
+            let len = self.len()?;
+            let mut bytes = Vec::new();
+            (&mut self.input).take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() < len {
+                bail!("unexpected end of file");
+            }
+            names.push(String::from_utf8(bytes)?);
+        }
+        Ok(Table::from_vec(names))
+    }
+
+    /// A restriction for each of len elements.
+    fn restrictor<D>(&mut self, len: usize, num_values: usize)
+        -> Result<Table<D, Restriction>>
+    {
+        let mut restrictions = Vec::with_capacity(len);
+        for _ in 0..len {
+            restrictions.push(self.restriction(num_values)?);
+        }
+        Ok(Table::from_vec(restrictions))
+    }
+
+    fn restriction(&mut self, num_values: usize) -> Result<Restriction> {
+        let kind = self.input.read_u8()?;
+        let values = self.nums(num_values)?;
+        match kind {
+            0 => Ok(Restriction::Not(values)),
+            1 => Ok(Restriction::Only(values)),
+            _ => bail!("invalid restriction kind: {}", kind),
+        }
+    }
+
+    fn nums<D>(&mut self, count: usize) -> Result<Vec<Num<D>>> {
+        let mut nums = Vec::new();
+        for _ in 0..self.len()? {
+            nums.push(self.num(count)?);
+        }
+        Ok(nums)
+    }
+
+    fn num<D>(&mut self, count: usize) -> Result<Num<D>> {
+        let num = self.input.read_u32::<LittleEndian>()? as usize;
+        if num >= count {
+            bail!("number {} out of range, expected less than {}", num, count);
+        }
+        Ok(to_num(num))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        Ok(self.input.read_u32::<LittleEndian>()? as usize)
+    }
+}
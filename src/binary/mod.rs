@@ -0,0 +1,226 @@
+mod domain;
+
+
+use data::{Domain, Fingerprint};
+use json;
+use Result;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use failure::{self, ResultExt};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use self::domain::{Decoder, Encoder};
+
+/// Marks a file as a compiled domain.
+const MAGIC: &[u8; 8] = b"LAYOUTGN";
+/// Changes with the format, so that files in an older format are ignored.
+const VERSION: u32 = 1;
+
+
+/// Where the compiled form of a domain file is kept.
+pub fn compiled_path(path: &str) -> String {
+    format!("{}.bin", path)
+}
+
+/// Compile the domain file at path, and return where the result was written.
+pub fn compile(path: &str) -> Result<String> {
+    let source = fs::read(path)?;
+    let domain = json::read_config(path)?;
+
+    let compiled = compiled_path(path);
+    let file = File::create(&compiled)
+        .context(format!("Could not create {}", compiled))?;
+    let mut out = BufWriter::new(file);
+    write_domain(&mut out, &domain, Fingerprint::of_bytes(&source))?;
+    out.flush()?;
+    Ok(compiled)
+}
+
+/// What became of the compiled form of a domain file when reading it.
+#[derive(Debug)]
+pub enum Compiled {
+    /// The domain was read from its compiled form.
+    Used,
+    /// There is no compiled form.
+    Missing,
+    /// The compiled form was compiled from other contents of the file, or in
+    /// another format.
+    OutOfDate,
+    /// The compiled form could not be read.
+    Unreadable(failure::Error),
+}
+
+/// Read the domain file at path, from its compiled form when that was
+/// compiled from the current contents of the file. Falls back to the file
+/// itself when the compiled form is out of date or can't be read.
+pub fn read_config(path: &str) -> Result<(Domain, Compiled)> {
+    let compiled = compiled_path(path);
+    let status = if Path::new(&compiled).exists() {
+        let source = fs::read(path)?;
+        let domain = File::open(&compiled).map_err(failure::Error::from)
+            .and_then(|file| {
+                read_domain(BufReader::new(file), Fingerprint::of_bytes(&source))
+            });
+        match domain {
+            Ok(Some(domain)) => return Ok((domain, Compiled::Used)),
+            Ok(None) => Compiled::OutOfDate,
+            Err(err) => Compiled::Unreadable(err),
+        }
+    } else {
+        Compiled::Missing
+    };
+    Ok((json::read_config(path)?, status))
+}
+
+/// Write a domain, along with the fingerprint of the file it was read from.
+pub fn write_domain<W: Write>(mut out: W, domain: &Domain, source_hash: u64)
+    -> Result<()>
+{
+    out.write_all(MAGIC)?;
+    out.write_u32::<LittleEndian>(VERSION)?;
+    out.write_u64::<LittleEndian>(source_hash)?;
+    Encoder::new(out).domain(domain)
+}
+
+/// Read a compiled domain. Returns None when it was compiled from a different
+/// source, or in a different format.
+pub fn read_domain<R: Read>(mut input: R, source_hash: u64)
+    -> Result<Option<Domain>>
+{
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("not a compiled domain");
+    }
+    if input.read_u32::<LittleEndian>()? != VERSION {
+        return Ok(None);
+    }
+    if input.read_u64::<LittleEndian>()? != source_hash {
+        return Ok(None);
+    }
+
+    let domain = Decoder::new(&mut input).domain()?;
+    if input.read(&mut [0])? != 0 {
+        bail!("unexpected data after the domain");
+    }
+    Ok(Some(domain))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cat::*;
+    use data::Cardinality;
+    use std::env;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn compiled(domain: &Domain, source_hash: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_domain(&mut out, domain, source_hash).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &path in ["abcABC.json", "petersen.json", "sudoku.json"].iter() {
+            let domain = json::read_config(path).unwrap();
+            let bytes = compiled(&domain, 42);
+            let read = read_domain(&bytes[..], 42).unwrap().unwrap();
+            assert_eq!(read.keys, domain.keys);
+            assert_eq!(read.values, domain.values);
+            assert_eq!(read.fingerprint(), domain.fingerprint());
+
+            // compiled from another version of the source
+            assert!(read_domain(&bytes[..], 43).unwrap().is_none());
+        }
+
+        // only the constrained key pairs are stored
+        let domain = json::read_config("sudoku.json").unwrap();
+        assert!(compiled(&domain, 42).len() < 100_000);
+    }
+
+    #[test]
+    fn test_damaged() {
+        let mut domain = json::read_config("abcABC.json").unwrap();
+        domain.cardinality.push(Cardinality {
+            value: domain.values.nums().next().unwrap(),
+            min: 1,
+            max: Some(2),
+        });
+        let bytes = compiled(&domain, 42);
+        let read = read_domain(&bytes[..], 42).unwrap().unwrap();
+        assert_eq!(read.cardinality, domain.cardinality);
+
+        assert!(read_domain(&bytes[..bytes.len() - 1], 42).is_err());
+        assert!(read_domain(&[&bytes[..], &[0]].concat()[..], 42).is_err());
+        assert!(read_domain(&b"{\"keys\": []}"[..], 42).is_err());
+
+        // the value of the cardinality bound, out of range: it is followed
+        // by the minimum, a flag and the maximum
+        let mut bytes = bytes;
+        let pos = bytes.len() - 8 - 1 - 8 - 4;
+        bytes[pos] = 200;
+        assert!(read_domain(&bytes[..], 42).is_err());
+    }
+
+    static NUM_SOURCES: AtomicUsize = AtomicUsize::new(0);
+
+    /// A copy of the domain file at path, under a name of its own.
+    fn copy_source(path: &str) -> String {
+        let num = NUM_SOURCES.fetch_add(1, Ordering::SeqCst);
+        let name = format!("layoutgen_compiled_{}_{}.json", process::id(), num);
+        let source = env::temp_dir().join(name);
+        let source = source.to_str().unwrap().to_string();
+        fs::copy(path, &source).unwrap();
+        source
+    }
+
+    #[test]
+    fn test_read_config() {
+        let source = copy_source("petersen.json");
+        let source = &source[..];
+
+        let (_, status) = read_config(source).unwrap();
+        assert!(matches!(status, Compiled::Missing));
+
+        let path = compile(source).unwrap();
+        assert_eq!(path, compiled_path(source));
+        let (domain, status) = read_config(source).unwrap();
+        assert!(matches!(status, Compiled::Used));
+        assert_eq!(domain.fingerprint(), json::read_config(source).unwrap().fingerprint());
+
+        // a stale compiled domain is ignored
+        let mut contents = fs::read_to_string(source).unwrap();
+        contents = contents.replace("\"values\": [", "\"values\": [\"extra\", ");
+        fs::write(source, &contents).unwrap();
+        let (domain, status) = read_config(source).unwrap();
+        assert!(matches!(status, Compiled::OutOfDate));
+        assert_eq!(domain.values.count().as_usize(), 4);
+
+        fs::remove_file(source).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_damaged_config() {
+        let source = copy_source("petersen.json");
+        let source = &source[..];
+        let expected = json::read_config(source).unwrap().fingerprint();
+
+        // garbage, and a compiled domain cut short
+        let path = compile(source).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        for contents in [&b"garbage"[..], &bytes[..bytes.len() / 2]].iter() {
+            fs::write(&path, contents).unwrap();
+            let (domain, status) = read_config(source).unwrap();
+            assert!(matches!(status, Compiled::Unreadable(_)));
+            assert_eq!(domain.fingerprint(), expected);
+        }
+
+        fs::remove_file(source).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
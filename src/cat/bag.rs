@@ -7,11 +7,11 @@ pub fn choose(n: usize, k: usize) -> usize {
         num *= n - (k-i);
         num /= i;
     }
-    return num;
+    num
 }
 
 pub fn choose_repeat(n: usize, k: usize) -> usize {
-    return choose(n + k - 1, k);
+    choose(n + k - 1, k)
 }
 
 
@@ -26,11 +26,11 @@ impl<T> Bag<T> {
         where T: Ord
     {
         vec.sort_by(|a, b| a.cmp(b).reverse());
-        return Bag::from_sorted(vec);
+        Bag::from_sorted(vec)
     }
 
     pub fn from_sorted(vec: Vec<T>) -> Self {
-        return Bag { elems: vec };
+        Bag { elems: vec }
     }
 }
 
@@ -43,7 +43,7 @@ impl<D> BagNum<D> {
     pub fn new(count: Count<D>, size: usize) -> Self {
         BagNum {
             elemtype_count: count,
-            size: size,
+            size,
         }
     }
 }
@@ -51,7 +51,7 @@ impl<D> BagNum<D> {
 impl<D> HasCount<Bag<D>> for BagNum<D> {
     fn count(&self) -> Count<Bag<D>> {
         let count = choose_repeat(self.elemtype_count.as_usize(), self.size);
-        return to_count(count);
+        to_count(count)
     }
 }
 
@@ -67,7 +67,7 @@ impl<D> Mapping<Bag<Num<D>>> for BagNum<D> {
                 num += choose_repeat(c, k)
             }
         }
-        return to_num(num);
+        to_num(num)
     }
 }
 
@@ -113,10 +113,10 @@ impl<D> SeqBag<D> {
             SeqIter::new(count, bag_size)
                 .map(|seq| bag_num.apply(Bag::new(seq)))
                 .collect());
-        return SeqBag {
-            count: count,
-            bag_size: bag_size,
-            seq_bag: seq_bag,
+        SeqBag {
+            count,
+            bag_size,
+            seq_bag,
         }
     }
 }
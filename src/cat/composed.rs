@@ -16,8 +16,8 @@ pub struct Composed<A, B>
 impl<A, B> Composed<A, B> {
     pub fn new(fst: A, snd: B) -> Self {
         Composed {
-            fst: fst,
-            snd: snd,
+            fst,
+            snd,
         }
     }
 }
@@ -39,9 +39,9 @@ impl<K, A, B> Index<K> for Composed<A, B>
 {
     type Output = B::Output;
 
-    fn index<'t>(&'t self, key: K) -> &'t B::Output {
+    fn index(&self, key: K) -> &B::Output {
         let idx = self.fst.apply(key);
-        return &self.snd[idx];
+        &self.snd[idx]
     }
 }
 
@@ -49,9 +49,9 @@ impl<K, A, B> IndexMut<K> for Composed<A, B>
     where A: Mapping<K>,
           B: IndexMut<A::Result>
 {
-    fn index_mut<'t>(&'t mut self, key: K) -> &'t mut B::Output {
+    fn index_mut(&mut self, key: K) -> &mut B::Output {
         let idx = self.fst.apply(key);
-        return &mut self.snd[idx];
+        &mut self.snd[idx]
     }
 }
 
@@ -70,7 +70,7 @@ impl<D> Copy for Count<D> {}
 
 pub fn to_count<D>(count: usize) -> Count<D> {
     Count {
-        count: count,
+        count,
         phantom: PhantomData,
     }
 }
@@ -80,7 +80,7 @@ impl<A, B> HasCount<(A, B)> for (Count<A>, Count<B>)
     fn count(&self) -> Count<(A, B)> {
         let &(major_count, minor_count) = self;
         let count = major_count.as_usize() * minor_count.as_usize();
-        return to_count(count);
+        to_count(count)
     }
 }
 
@@ -95,11 +95,11 @@ impl<D> Iterator for Enumerator<D> {
 
     fn next(&mut self) -> Option<Num<D>> {
         if self.pos >= self.count.as_usize() {
-            return None;
+            None
         } else {
             let num = to_num(self.pos);
             self.pos += 1;
-            return Some(num);
+            Some(num)
         }
     }
 }
@@ -60,7 +60,7 @@ impl<D> Hash for Num<D> {
 
 pub fn to_num<D>(num: usize) -> Num<D> {
     Num {
-        num: num,
+        num,
         phantom: PhantomData,
     }
 }
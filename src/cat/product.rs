@@ -20,7 +20,7 @@ impl<Maj, Min> Mapping<(Num<Maj>, Num<Min>)> for ProductNum<Maj, Min> {
         let (maj_num, min_num) = elem;
         let maj_component = self.minor_count.as_usize() * maj_num.as_usize();
         let min_component = min_num.as_usize();
-        return cat::internal::to_num(maj_component + min_component);
+        cat::internal::to_num(maj_component + min_component)
     }
 }
 
@@ -30,13 +30,13 @@ impl<Maj, Min> Mapping<Num<Product<Maj, Min>>> for ProductNum<Maj, Min> {
     fn apply(&self, num: Num<Product<Maj, Min>>) -> (Num<Maj>, Num<Min>) {
         let maj_num = num.as_usize() / self.minor_count.as_usize();
         let min_num = num.as_usize() % self.minor_count.as_usize();
-        return (cat::internal::to_num(maj_num), cat::internal::to_num(min_num));
+        (cat::internal::to_num(maj_num), cat::internal::to_num(min_num))
     }
 }
 
 impl<Maj, Min> HasCount<Product<Maj, Min>> for ProductNum<Maj, Min> {
     fn count(&self) -> Count<Product<Maj, Min>> {
         let count = self.major_count.as_usize() * self.minor_count.as_usize();
-        return cat::internal::to_count(count);
+        cat::internal::to_count(count)
     }
 }
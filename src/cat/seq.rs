@@ -18,7 +18,7 @@ impl<D> SeqIter<D> {
     pub fn new(count: Count<D>, len: usize) -> Self {
         SeqIter {
             idxs: vec![0; len],
-            count: count,
+            count,
         }
     }
 
@@ -40,11 +40,11 @@ impl<D> Iterator for SeqIter<D> {
 
     fn next(&mut self) -> Option<Vec<Num<D>>> {
         if self.idxs[0] >= self.count.as_usize() {
-            return None;
+            None
         } else {
             let item = self.idxs.iter().map(|&num| to_num(num)).collect();
             self.increment();
-            return Some(item);
+            Some(item)
         }
     }
 }
@@ -61,8 +61,8 @@ pub struct SeqNum<D> {
 impl<D> SeqNum<D> {
     pub fn new(count: Count<D>, len: usize) -> Self {
         SeqNum {
-            count: count,
-            len: len,
+            count,
+            len,
         }
     }
 }
@@ -80,6 +80,6 @@ impl<D, I> Mapping<I> for SeqNum<D>
 
     fn apply(&self, seq: I) -> Num<Seq<D>> {
         let num = seq.into_iter().fold(0, |acc, num| acc * self.count.as_usize() + num.as_usize());
-        return to_num(num);
+        to_num(num)
     }
 }
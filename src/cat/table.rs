@@ -39,20 +39,20 @@ impl<D, T> Table<D, T> {
 impl<D, T> Index<Num<D>> for Table<D, T> {
     type Output = T;
 
-    fn index<'t>(&'t self, num: Num<D>) -> &'t T {
-        return &self.elems[num.as_usize()];
+    fn index(&self, num: Num<D>) -> &T {
+        &self.elems[num.as_usize()]
     }
 }
 
 impl<D, T> IndexMut<Num<D>> for Table<D, T> {
-    fn index_mut<'t>(&'t mut self, num: Num<D>) -> &'t mut T {
-        return &mut self.elems[num.as_usize()];
+    fn index_mut(&mut self, num: Num<D>) -> &mut T {
+        &mut self.elems[num.as_usize()]
     }
 }
 
 impl<D, T> HasCount<D> for Table<D, T> {
     fn count(&self) -> Count<D> {
-        return to_count(self.elems.len());
+        to_count(self.elems.len())
     }
 }
 
@@ -9,7 +9,7 @@ pub trait Mapping<T> {
 }
 
 pub trait Dict<K> : Index<K> + IndexMut<K> {
-    fn borrow_mut<'a>(&'a mut self) -> BorrowedMut<Self>
+    fn borrow_mut<'a>(&'a mut self) -> BorrowedMut<'a, Self>
         where Self: Sized
     {
         BorrowedMut { a: self }
@@ -74,7 +74,7 @@ impl<'a, A: 'a, K> Index<K> for BorrowedMut<'a, A>
 {
     type Output = A::Output;
 
-    fn index<'t>(&'t self, key: K) -> &'t A::Output {
+    fn index(&self, key: K) -> &A::Output {
         &self.a[key]
     }
 }
@@ -82,7 +82,7 @@ impl<'a, A: 'a, K> Index<K> for BorrowedMut<'a, A>
 impl<'a, A: 'a, K> IndexMut<K> for BorrowedMut<'a, A>
     where A: IndexMut<K>
 {
-    fn index_mut<'t>(&'t mut self, key: K) -> &'t mut A::Output {
+    fn index_mut(&mut self, key: K) -> &mut A::Output {
         &mut self.a[key]
     }
 }
@@ -0,0 +1,412 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::Duration;
+
+use Result;
+use algorithm::{Limits, Restarts, ValueHeuristic, VariableHeuristic};
+
+pub const USAGE: &str = "\
+usage: keyboard_optimizer_layoutgen <command> [options]
+
+commands:
+    solve <domain.json>     find a layout satisfying the domain
+        -o, --output <path>     write the solution to <path> instead of stdout
+        -a, --all               enumerate all solutions
+        -n, --max-solutions <n> enumerate at most <n> solutions
+        -c, --count             only report the number of solutions; all of
+                                them are counted unless -n is given
+        --format <format>       text (default), or json for an object with
+                                the status, solutions and search statistics
+        --pins <path>           keep the keys in <path>, a map from key to
+                                value names, fixed at their value
+        --pin <key>=<value>     keep <key> fixed at <value>; can be repeated,
+                                and takes precedence over --pins
+        --trace <level>         trace the search on stderr: 1 for solutions
+                                and restarts, 2 for wipeouts and backtracks
+                                as well, 3 for assignments too
+        --trace-file <path>     write the trace to <path> as JSON lines
+                                instead, at level 3 unless --trace is given
+        --nogoods <path>        start from the nogoods stored in <path>, and
+                                store the learned nogoods there afterwards;
+                                they are kept when the domain only restricts
+                                the values of keys further, and dropped on
+                                any other change to it
+        --max-nodes <n>         give up after trying <n> values
+        --max-backtracks <n>    give up after <n> backtracks
+        --time-limit <seconds>  give up after searching for <seconds>
+        --threads <n>           search on <n> threads (default 1); cannot be
+                                combined with --nogoods, --trace,
+                                --trace-file, --max-nodes or --max-backtracks
+        --seed <n>              try values in a random order determined by <n>
+        --restarts <strategy>   restart the search after a number of
+                                backtracks; one of none, luby[:<unit>] or
+                                geometric[:<base>[:<factor>]]
+        --var-order <order>     how to pick the next key; one of mrv
+                                (default), degree or dom-wdeg
+        --value-order <order>   how to order the values for a key; one of
+                                range (default), lcv or scores:<path>, where
+                                <path> holds scores per key and value
+    check <domain.json>     report whether the domain is satisfiable
+    explain <domain.json>   print the parts of the domain file behind a
+                            minimal set of restrictions and constraints
+                            that make the domain unsatisfiable
+    compile <domain.json>   store the domain in a binary form next to it,
+                            as <domain.json>.bin; the other commands read
+                            that instead, as long as the domain is unchanged
+    inspect <domain.json>   print a summary of the domain
+    lint <domain.json>      report suspicious restrictions and constraints
+    help                    print this message
+
+exit codes:
+    0   a solution was found (or the command succeeded)
+    1   an error occurred
+    2   the domain is unsatisfiable
+    3   the search gave up at one of its limits
+";
+
+pub enum Command {
+    Solve(Box<SolveArgs>),
+    Check(DomainArgs),
+    Explain(DomainArgs),
+    Compile(DomainArgs),
+    Inspect(DomainArgs),
+    Lint(DomainArgs),
+    Help,
+}
+
+/// How solve writes its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = ::failure::Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        Ok(match s {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            _ => bail!("unknown format: {}", s),
+        })
+    }
+}
+
+pub struct DomainArgs {
+    pub domain: String,
+}
+
+pub struct SolveArgs {
+    pub domain: String,
+    pub output: Option<String>,
+    /// Maximum number of solutions to enumerate; None for all of them.
+    pub max_solutions: Option<usize>,
+    pub count_only: bool,
+    pub format: Format,
+    pub pins: Option<String>,
+    /// Pins given on the command line, as key and value names.
+    pub pin: Vec<(String, String)>,
+    pub nogoods: Option<String>,
+    /// Trace verbosity; None for no trace.
+    pub trace: Option<usize>,
+    pub trace_file: Option<String>,
+    pub limits: Limits,
+    pub threads: usize,
+    pub seed: Option<u64>,
+    pub restarts: Restarts,
+    pub var_order: VariableHeuristic,
+    pub value_order: ValueHeuristic,
+}
+
+/// Parse a command from the program arguments, excluding the program name.
+pub fn parse_args<I>(args: I) -> Result<Command>
+    where I: Iterator<Item = String>
+{
+    let mut args = Args { args: args.collect() };
+
+    let command = match args.next() {
+        None => return Ok(Command::Help),
+        Some(command) => command,
+    };
+
+    match command.as_str() {
+        "solve" => parse_solve(args).map(|args| Command::Solve(Box::new(args))),
+        "check" => parse_domain_args(args).map(Command::Check),
+        "explain" => parse_domain_args(args).map(Command::Explain),
+        "compile" => parse_domain_args(args).map(Command::Compile),
+        "inspect" => parse_domain_args(args).map(Command::Inspect),
+        "lint" => parse_domain_args(args).map(Command::Lint),
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        _ => bail!("unknown command: {}", command),
+    }
+}
+
+fn parse_solve(mut args: Args) -> Result<SolveArgs> {
+    let mut domain = None;
+    let mut output = None;
+    let mut max_solutions = Some(1);
+    // whether -a or -n was given
+    let mut solutions_given = false;
+    let mut count_only = false;
+    let mut format = Format::Text;
+    let mut pins = None;
+    let mut pin = Vec::new();
+    let mut nogoods = None;
+    let mut trace = None;
+    let mut trace_file = None;
+    let mut limits = Limits::none();
+    let mut threads = 1;
+    let mut seed = None;
+    let mut restarts = Restarts::Never;
+    let mut var_order = VariableHeuristic::Mrv;
+    let mut value_order = ValueHeuristic::Range;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.value_for(&arg)?),
+            "-a" | "--all" => {
+                max_solutions = None;
+                solutions_given = true;
+            }
+            "-n" | "--max-solutions" => {
+                max_solutions = Some(args.parsed_value_for(&arg)?);
+                solutions_given = true;
+            }
+            "-c" | "--count" => count_only = true,
+            "--format" => format = args.value_for(&arg)?.parse()?,
+            "--pins" => pins = Some(args.value_for(&arg)?),
+            "--pin" => pin.push(parse_pin(&args.value_for(&arg)?)?),
+            "--nogoods" => nogoods = Some(args.value_for(&arg)?),
+            "--trace" => trace = Some(args.parsed_value_for(&arg)?),
+            "--trace-file" => trace_file = Some(args.value_for(&arg)?),
+            "--max-nodes" => limits.nodes = Some(args.parsed_value_for(&arg)?),
+            "--max-backtracks" => {
+                limits.backtracks = Some(args.parsed_value_for(&arg)?);
+            }
+            "--time-limit" => limits.time = Some(parse_seconds(&mut args, &arg)?),
+            "--threads" => {
+                threads = args.parsed_value_for(&arg)?;
+                if threads == 0 {
+                    bail!("invalid value for {}: 0", arg);
+                }
+            }
+            "--seed" => seed = Some(args.parsed_value_for(&arg)?),
+            "--restarts" => restarts = args.value_for(&arg)?.parse()?,
+            "--var-order" => var_order = args.value_for(&arg)?.parse()?,
+            "--value-order" => value_order = args.value_for(&arg)?.parse()?,
+            _ => args.positional(arg, &mut domain)?,
+        }
+    }
+
+    // counting a single solution is of little use
+    if count_only && !solutions_given {
+        max_solutions = None;
+    }
+
+    Ok(SolveArgs {
+        domain: required(domain, "domain")?,
+        output,
+        max_solutions,
+        count_only,
+        format,
+        pins,
+        pin,
+        nogoods,
+        trace,
+        trace_file,
+        limits,
+        threads,
+        seed,
+        restarts,
+        var_order,
+        value_order,
+    })
+}
+
+/// Parse a pin of the form key=value. The value may contain '=' itself.
+fn parse_pin(pin: &str) -> Result<(String, String)> {
+    match pin.split_once('=') {
+        Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => bail!("invalid pin: {}, expected <key>=<value>", pin),
+    }
+}
+
+fn parse_seconds(args: &mut Args, option: &str) -> Result<Duration> {
+    let seconds: f64 = args.parsed_value_for(option)?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        bail!("invalid value for {}: {}", option, seconds);
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn parse_domain_args(mut args: Args) -> Result<DomainArgs> {
+    let mut domain = None;
+
+    while let Some(arg) = args.next() {
+        args.positional(arg, &mut domain)?;
+    }
+
+    Ok(DomainArgs {
+        domain: required(domain, "domain")?,
+    })
+}
+
+fn required<T>(value: Option<T>, name: &str) -> Result<T> {
+    match value {
+        Some(value) => Ok(value),
+        None => bail!("missing argument: {}", name),
+    }
+}
+
+struct Args {
+    args: VecDeque<String>,
+}
+
+impl Args {
+    fn next(&mut self) -> Option<String> {
+        self.args.pop_front()
+    }
+
+    /// Take the value following an option.
+    fn value_for(&mut self, option: &str) -> Result<String> {
+        match self.next() {
+            Some(value) => Ok(value),
+            None => bail!("option {} requires a value", option),
+        }
+    }
+
+    fn parsed_value_for<T>(&mut self, option: &str) -> Result<T>
+        where T: FromStr
+    {
+        let value = self.value_for(option)?;
+        match value.parse() {
+            Ok(parsed) => Ok(parsed),
+            Err(_) => bail!("invalid value for {}: {}", option, value),
+        }
+    }
+
+    /// Store a positional argument, rejecting unknown options and surplus
+    /// positionals.
+    fn positional(&self, arg: String, slot: &mut Option<String>)
+        -> Result<()>
+    {
+        if arg.starts_with('-') {
+            bail!("unknown option: {}", arg);
+        }
+        if slot.is_some() {
+            bail!("unexpected argument: {}", arg);
+        }
+        *slot = Some(arg);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_solve() {
+        match parse(&["solve", "abcABC.json", "-o", "out.txt"]).unwrap() {
+            Command::Solve(args) => {
+                assert_eq!(args.domain, "abcABC.json");
+                assert_eq!(args.output, Some("out.txt".to_string()));
+                assert_eq!(args.max_solutions, Some(1));
+            }
+            _ => panic!("expected solve command"),
+        }
+
+        match parse(&["solve", "--all", "--count", "abcABC.json"]).unwrap() {
+            Command::Solve(args) => {
+                assert_eq!(args.max_solutions, None);
+                assert!(args.count_only);
+                assert_eq!(args.format, Format::Text);
+                assert_eq!(args.seed, None);
+                assert_eq!(args.trace, None);
+                assert_eq!(args.limits, Limits::none());
+                assert_eq!(args.threads, 1);
+                assert_eq!(args.restarts, Restarts::Never);
+            }
+            _ => panic!("expected solve command"),
+        }
+
+        match parse(&["solve", "-c", "abcABC.json"]).unwrap() {
+            Command::Solve(args) => assert_eq!(args.max_solutions, None),
+            _ => panic!("expected solve command"),
+        }
+        match parse(&["solve", "-c", "-n", "5", "abcABC.json"]).unwrap() {
+            Command::Solve(args) => assert_eq!(args.max_solutions, Some(5)),
+            _ => panic!("expected solve command"),
+        }
+
+        let args = ["solve", "a.json", "--seed", "42", "--restarts", "luby:50",
+                    "--format", "json", "--threads", "8"];
+        match parse(&args).unwrap() {
+            Command::Solve(args) => {
+                assert_eq!(args.format, Format::Json);
+                assert_eq!(args.threads, 8);
+                assert_eq!(args.seed, Some(42));
+                assert_eq!(args.restarts, Restarts::Luby { unit: 50 });
+            }
+            _ => panic!("expected solve command"),
+        }
+
+        let args = ["solve", "a.json", "--var-order", "dom-wdeg",
+                    "--value-order", "scores:s.json"];
+        match parse(&args).unwrap() {
+            Command::Solve(args) => {
+                assert_eq!(args.var_order, VariableHeuristic::DomWdeg);
+                assert_eq!(args.value_order,
+                           ValueHeuristic::Scores("s.json".to_string()));
+            }
+            _ => panic!("expected solve command"),
+        }
+
+        let args = ["solve", "a.json", "--pins", "p.json",
+                    "--pin", "1/lower=z", "--pin", "2/lower==",
+                    "--trace", "2", "--trace-file", "trace.jsonl",
+                    "--max-nodes", "1000", "--time-limit", "1.5"];
+        match parse(&args).unwrap() {
+            Command::Solve(args) => {
+                assert_eq!(args.trace, Some(2));
+                assert_eq!(args.trace_file, Some("trace.jsonl".to_string()));
+                assert_eq!(args.limits, Limits {
+                    nodes: Some(1000),
+                    backtracks: None,
+                    time: Some(Duration::from_millis(1500)),
+                });
+                assert_eq!(args.pins, Some("p.json".to_string()));
+                assert_eq!(args.pin, [
+                    ("1/lower".to_string(), "z".to_string()),
+                    ("2/lower".to_string(), "=".to_string()),
+                ]);
+            }
+            _ => panic!("expected solve command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(&["solve"]).is_err());
+        assert!(parse(&["solve", "a.json", "b.json"]).is_err());
+        assert!(parse(&["solve", "a.json", "--bogus"]).is_err());
+        assert!(parse(&["solve", "a.json", "--output"]).is_err());
+        assert!(parse(&["solve", "a.json", "-n", "many"]).is_err());
+        assert!(parse(&["solve", "a.json", "--restarts", "often"]).is_err());
+        assert!(parse(&["solve", "a.json", "--var-order", "any"]).is_err());
+        assert!(parse(&["solve", "a.json", "--format", "xml"]).is_err());
+        assert!(parse(&["solve", "a.json", "--pin", "1/lower"]).is_err());
+        assert!(parse(&["solve", "a.json", "--pin", "=z"]).is_err());
+        assert!(parse(&["solve", "a.json", "--time-limit", "-1"]).is_err());
+        assert!(parse(&["solve", "a.json", "--threads", "0"]).is_err());
+        assert!(parse(&["compile"]).is_err());
+        assert!(parse(&["frobnicate"]).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use super::types::*;
+
+use cat::*;
+
+use std::collections::HashMap;
+
+/// Keys whose ranges only contain values from a set that is no larger than
+/// the set of keys itself.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HallSet {
+    pub keys: Vec<Num<Key>>,
+    pub values: Vec<Num<Value>>,
+}
+
+/// Outcome of filtering an all-different group.
+#[derive(Debug)]
+pub enum Filtering {
+    /// The keys cannot all get a different value: the values of the Hall
+    /// set are outnumbered by its keys.
+    Infeasible(HallSet),
+    /// The assignments that are part of no solution of the group, each with
+    /// the index of the Hall set that takes its value.
+    Consistent {
+        hall_sets: Vec<HallSet>,
+        pruned: Vec<(Assignment, usize)>,
+    },
+}
+
+/// Régin's filtering algorithm: find a matching of the keys to distinct
+/// values, and prune the assignments that are part of no such matching.
+/// A value may be taken by as many keys as its capacity allows; None means
+/// any number of keys.
+pub fn filter<'a, F, C>(keys: &[Num<Key>], capacity: C, range_for: F)
+    -> Filtering
+    where F: Fn(Num<Key>) -> &'a [Num<Value>],
+          C: Fn(Num<Value>) -> Option<usize>
+{
+    let graph = Graph::new(keys, capacity, range_for);
+    let mut matching = Matching::new(&graph);
+
+    for key_idx in 0..graph.num_keys() {
+        let mut seen = vec![false; graph.num_values()];
+        if !matching.augment(&graph, key_idx, &mut seen) {
+            // the values that were reached are all taken by other keys
+            // that were reached as well.
+            let values: Vec<usize> = (0..graph.num_values())
+                .filter(|&v| seen[v])
+                .collect();
+            let mut keys: Vec<usize> = values.iter()
+                .map(|&v| matching.value_match[v].unwrap())
+                .collect();
+            keys.push(key_idx);
+            return Filtering::Infeasible(graph.hall_set(&keys, &values));
+        }
+    }
+
+    let residual = Residual::new(&graph, &matching);
+    let mut hall_sets = Vec::new();
+    // hall set index by the value it was grown from
+    let mut hall_set_for: HashMap<usize, usize> = HashMap::new();
+    let mut pruned: Vec<(Assignment, usize)> = Vec::new();
+
+    for key_idx in 0..graph.num_keys() {
+        let matched = matching.key_match[key_idx].map(|m| graph.values[m]);
+        for &v in graph.adj[key_idx].iter() {
+            // the copies of a value all get pruned, or none of them
+            if matched == Some(graph.values[v]) {
+                continue;
+            }
+            let node = residual.value_node(v);
+            if residual.reaches_free[node] ||
+                residual.component[node] == residual.component[key_idx]
+            {
+                continue;
+            }
+
+            let idx = *hall_set_for.entry(v).or_insert_with(|| {
+                let (keys, values) = residual.reachable_from(node);
+                hall_sets.push(graph.hall_set(&keys, &values));
+                hall_sets.len() - 1
+            });
+            let a = Assignment {
+                key_num: graph.keys[key_idx],
+                value_num: graph.values[v],
+            };
+            if pruned.iter().all(|&(other, _)| other != a) {
+                pruned.push((a, idx));
+            }
+        }
+    }
+
+    Filtering::Consistent { hall_sets, pruned }
+}
+
+/// Bipartite graph between the keys of a group and the values in their
+/// ranges, numbered locally. A value gets as many copies as its capacity,
+/// but no more than there are keys, as no more can be taken; without a
+/// capacity, every key gets a copy of its own so that it never runs out.
+struct Graph {
+    keys: Vec<Num<Key>>,
+    values: Vec<Num<Value>>,
+    // local values in the range of each key
+    adj: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn new<'a, F, C>(keys: &[Num<Key>], capacity: C, range_for: F) -> Self
+        where F: Fn(Num<Key>) -> &'a [Num<Value>],
+              C: Fn(Num<Value>) -> Option<usize>
+    {
+        let mut values = Vec::new();
+        // the local copies of each value
+        let mut value_idx = HashMap::new();
+        let adj = keys.iter().map(|&key_num| {
+            let mut adj = Vec::new();
+            for &value_num in range_for(key_num).iter() {
+                let copies = match capacity(value_num) {
+                    None => {
+                        values.push(value_num);
+                        (values.len() - 1)..values.len()
+                    }
+                    Some(capacity) => {
+                        let num_copies = capacity.min(keys.len());
+                        value_idx.entry(value_num).or_insert_with(|| {
+                            let start = values.len();
+                            values.extend((0..num_copies).map(|_| value_num));
+                            start..values.len()
+                        }).clone()
+                    }
+                };
+                adj.extend(copies);
+            }
+            adj
+        }).collect();
+
+        Graph { keys: keys.to_vec(), values, adj }
+    }
+
+    fn num_keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn num_values(&self) -> usize {
+        self.values.len()
+    }
+
+    fn hall_set(&self, keys: &[usize], values: &[usize]) -> HallSet {
+        let mut value_nums = Vec::new();
+        for &v in values.iter() {
+            if !value_nums.contains(&self.values[v]) {
+                value_nums.push(self.values[v]);
+            }
+        }
+        HallSet {
+            keys: keys.iter().map(|&k| self.keys[k]).collect(),
+            values: value_nums,
+        }
+    }
+}
+
+struct Matching {
+    key_match: Vec<Option<usize>>,
+    value_match: Vec<Option<usize>>,
+}
+
+impl Matching {
+    fn new(graph: &Graph) -> Self {
+        Matching {
+            key_match: vec![None; graph.num_keys()],
+            value_match: vec![None; graph.num_values()],
+        }
+    }
+
+    /// Try to match key_idx along an augmenting path.
+    fn augment(&mut self, graph: &Graph, key_idx: usize, seen: &mut [bool])
+        -> bool
+    {
+        for &v in graph.adj[key_idx].iter() {
+            if seen[v] {
+                continue;
+            }
+            seen[v] = true;
+
+            let free = match self.value_match[v] {
+                None => true,
+                Some(other) => self.augment(graph, other, seen),
+            };
+            if free {
+                self.value_match[v] = Some(key_idx);
+                self.key_match[key_idx] = Some(v);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// The graph of alternating paths for a matching that covers all keys.
+/// Keys are nodes 0..k and values nodes k..k+m. A key points to the values
+/// in its range except its match; a matched value points to its key.
+/// An unmatched edge is part of some matching when it lies on a cycle, or
+/// when a free value can be reached from its value.
+struct Residual {
+    num_keys: usize,
+    succ: Vec<Vec<usize>>,
+    reaches_free: Vec<bool>,
+    component: Vec<usize>,
+}
+
+impl Residual {
+    fn new(graph: &Graph, matching: &Matching) -> Self {
+        let num_keys = graph.num_keys();
+        let num_nodes = num_keys + graph.num_values();
+
+        let mut succ = vec![Vec::new(); num_nodes];
+        let mut pred = vec![Vec::new(); num_nodes];
+        for key_idx in 0..num_keys {
+            for &v in graph.adj[key_idx].iter() {
+                let node = num_keys + v;
+                if matching.key_match[key_idx] == Some(v) {
+                    succ[node].push(key_idx);
+                    pred[key_idx].push(node);
+                } else {
+                    succ[key_idx].push(node);
+                    pred[node].push(key_idx);
+                }
+            }
+        }
+
+        let mut reaches_free = vec![false; num_nodes];
+        let mut stack: Vec<usize> = (0..graph.num_values())
+            .filter(|&v| matching.value_match[v].is_none())
+            .map(|v| num_keys + v)
+            .collect();
+        for &node in stack.iter() {
+            reaches_free[node] = true;
+        }
+        while let Some(node) = stack.pop() {
+            for &p in pred[node].iter() {
+                if !reaches_free[p] {
+                    reaches_free[p] = true;
+                    stack.push(p);
+                }
+            }
+        }
+
+        let component = strongly_connected_components(&succ);
+        Residual { num_keys, succ, reaches_free, component }
+    }
+
+    fn value_node(&self, v: usize) -> usize {
+        self.num_keys + v
+    }
+
+    /// The local keys and values that can be reached from node.
+    fn reachable_from(&self, node: usize) -> (Vec<usize>, Vec<usize>) {
+        let mut seen = vec![false; self.succ.len()];
+        seen[node] = true;
+        let mut stack = vec![node];
+        let (mut keys, mut values) = (Vec::new(), Vec::new());
+        while let Some(node) = stack.pop() {
+            if node < self.num_keys {
+                keys.push(node);
+            } else {
+                values.push(node - self.num_keys);
+            }
+            for &next in self.succ[node].iter() {
+                if !seen[next] {
+                    seen[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        (keys, values)
+    }
+}
+
+/// Tarjan's algorithm. Returns a component number for each node.
+fn strongly_connected_components(succ: &[Vec<usize>]) -> Vec<usize> {
+    struct Tarjan<'a> {
+        succ: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        lowlink: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        component: Vec<usize>,
+        next_index: usize,
+        num_components: usize,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, node: usize) {
+            self.index[node] = Some(self.next_index);
+            self.lowlink[node] = self.next_index;
+            self.next_index += 1;
+            self.stack.push(node);
+            self.on_stack[node] = true;
+
+            for &next in self.succ[node].iter() {
+                match self.index[next] {
+                    None => {
+                        self.visit(next);
+                        self.lowlink[node] = self.lowlink[node].min(self.lowlink[next]);
+                    }
+                    Some(idx) if self.on_stack[next] => {
+                        self.lowlink[node] = self.lowlink[node].min(idx);
+                    }
+                    Some(_) => (),
+                }
+            }
+
+            if Some(self.lowlink[node]) == self.index[node] {
+                loop {
+                    let member = self.stack.pop().unwrap();
+                    self.on_stack[member] = false;
+                    self.component[member] = self.num_components;
+                    if member == node {
+                        break;
+                    }
+                }
+                self.num_components += 1;
+            }
+        }
+    }
+
+    let num_nodes = succ.len();
+    let mut tarjan = Tarjan {
+        succ,
+        index: vec![None; num_nodes],
+        lowlink: vec![0; num_nodes],
+        on_stack: vec![false; num_nodes],
+        stack: Vec::new(),
+        component: vec![0; num_nodes],
+        next_index: 0,
+        num_components: 0,
+    };
+    for node in 0..num_nodes {
+        if tarjan.index[node].is_none() {
+            tarjan.visit(node);
+        }
+    }
+    tarjan.component
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cat::internal::to_num;
+
+    fn ranges(ranges: &[&[usize]]) -> (Vec<Num<Key>>, Vec<Vec<Num<Value>>>) {
+        let keys = (0..ranges.len()).map(to_num).collect();
+        let ranges = ranges.iter().map(|range| {
+            range.iter().cloned().map(to_num).collect()
+        }).collect();
+        (keys, ranges)
+    }
+
+    fn assignment(key: usize, value: usize) -> Assignment {
+        Assignment { key_num: to_num(key), value_num: to_num(value) }
+    }
+
+    #[test]
+    fn test_infeasible() {
+        // three keys sharing two values
+        let (keys, ranges) = ranges(&[&[0, 1], &[3], &[0, 1], &[0, 1]]);
+        match filter(&keys, |_| Some(1), |k| &ranges[k.as_usize()]) {
+            Filtering::Infeasible(mut hall_set) => {
+                hall_set.keys.sort();
+                hall_set.values.sort();
+                let keys: Vec<Num<Key>> = vec![to_num(0), to_num(2), to_num(3)];
+                assert_eq!(hall_set.keys, keys);
+                assert_eq!(hall_set.values, vec![to_num(0), to_num(1)]);
+            }
+            Filtering::Consistent { .. } => panic!("expected infeasible"),
+        }
+    }
+
+    #[test]
+    fn test_prune() {
+        // keys 0 and 1 take values 0 and 1 between them, so key 2 can only
+        // get value 2 or 3. Keys 2 and 3 can swap those, so key 3 keeps both
+        // of its values.
+        let (keys, ranges) = ranges(&[&[0, 1], &[0, 1], &[0, 1, 2, 3], &[2, 3]]);
+        match filter(&keys, |_| Some(1), |k| &ranges[k.as_usize()]) {
+            Filtering::Infeasible(_) => panic!("expected consistent"),
+            Filtering::Consistent { hall_sets, pruned } => {
+                let mut assignments: Vec<Assignment> = pruned.iter()
+                    .map(|&(a, _)| a)
+                    .collect();
+                assignments.sort_by_key(|a| a.value_num);
+                assert_eq!(assignments, [assignment(2, 0), assignment(2, 1)]);
+
+                for &(_, idx) in pruned.iter() {
+                    let mut hall_keys = hall_sets[idx].keys.clone();
+                    hall_keys.sort();
+                    assert_eq!(hall_keys, [to_num(0), to_num(1)]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_blank() {
+        // value 0 is blank, so keys 0 and 1 can both take it; keys 2 and 3
+        // take values 1 and 2 between them
+        let (keys, ranges) = ranges(&[&[0, 1], &[0], &[1, 2], &[1, 2]]);
+        let blank = |v: Num<Value>| if v.as_usize() == 0 { None } else { Some(1) };
+        match filter(&keys, blank, |k| &ranges[k.as_usize()]) {
+            Filtering::Infeasible(_) => panic!("expected consistent"),
+            Filtering::Consistent { pruned, .. } => {
+                let mut assignments: Vec<Assignment> = pruned.iter()
+                    .map(|&(a, _)| a)
+                    .collect();
+                assignments.sort_by_key(|a| a.key_num);
+                assert_eq!(assignments, [assignment(0, 1)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let capacity = |v: Num<Value>| Some(if v.as_usize() == 0 { 2 } else { 1 });
+
+        // value 0 can be taken twice, so keys 0 and 1 leave value 1 for
+        // key 2, and value 2 for key 3
+        let (keys, full) = ranges(&[&[0], &[0], &[0, 1], &[1, 2]]);
+        match filter(&keys, capacity, |k| &full[k.as_usize()]) {
+            Filtering::Infeasible(_) => panic!("expected consistent"),
+            Filtering::Consistent { pruned, .. } => {
+                let mut assignments: Vec<Assignment> = pruned.iter()
+                    .map(|&(a, _)| a)
+                    .collect();
+                assignments.sort_by_key(|a| a.key_num);
+                assert_eq!(assignments, [assignment(2, 0), assignment(3, 1)]);
+            }
+        }
+
+        // but a third key cannot get value 0
+        let (keys, full) = ranges(&[&[0], &[0], &[1], &[0, 1]]);
+        match filter(&keys, capacity, |k| &full[k.as_usize()]) {
+            Filtering::Infeasible(_) => (),
+            Filtering::Consistent { .. } => panic!("expected infeasible"),
+        }
+    }
+
+    #[test]
+    fn test_large_capacity() {
+        // a value that may be taken any number of times gets no more copies
+        // than there are keys
+        let (keys, ranges) = ranges(&[&[0], &[0, 1], &[0]]);
+        let capacity = |v: Num<Value>| Some(if v.as_usize() == 0 { usize::MAX } else { 1 });
+        let graph = Graph::new(&keys, capacity, |k| &ranges[k.as_usize()]);
+        assert_eq!(graph.num_values(), 4);
+
+        match filter(&keys, capacity, |k| &ranges[k.as_usize()]) {
+            Filtering::Infeasible(_) => panic!("expected consistent"),
+            Filtering::Consistent { pruned, .. } => assert!(pruned.is_empty()),
+        }
+    }
+
+    #[test]
+    fn test_permutation() {
+        // with as many values as keys, a fixed key takes its value from the
+        // others
+        let (keys, ranges) = ranges(&[&[1], &[0, 1, 2], &[0, 1, 2]]);
+        match filter(&keys, |_| Some(1), |k| &ranges[k.as_usize()]) {
+            Filtering::Infeasible(_) => panic!("expected consistent"),
+            Filtering::Consistent { pruned, .. } => {
+                let mut assignments: Vec<Assignment> = pruned.iter()
+                    .map(|&(a, _)| a)
+                    .collect();
+                assignments.sort_by_key(|a| a.key_num);
+                assert_eq!(assignments, [assignment(1, 1), assignment(2, 1)]);
+            }
+        }
+    }
+}
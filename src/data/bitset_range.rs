@@ -0,0 +1,476 @@
+use cat::*;
+use cat::internal::to_num;
+
+const WORD_BITS: usize = 64;
+
+/// A set of indices, one bit each.
+#[derive(Debug, Clone, PartialEq)]
+struct Bitset {
+    words: Vec<u64>,
+}
+
+impl Bitset {
+    fn empty(len: usize) -> Self {
+        Bitset {
+            words: vec![0; len.div_ceil(WORD_BITS)],
+        }
+    }
+
+    fn full(len: usize) -> Self {
+        let mut set = Bitset::empty(len);
+        for idx in 0..len {
+            set.insert(idx);
+        }
+        set
+    }
+
+    fn contains(&self, idx: usize) -> bool {
+        self.words[idx / WORD_BITS] & (1 << (idx % WORD_BITS)) != 0
+    }
+
+    fn insert(&mut self, idx: usize) {
+        self.words[idx / WORD_BITS] |= 1 << (idx % WORD_BITS);
+    }
+
+    fn remove(&mut self, idx: usize) {
+        self.words[idx / WORD_BITS] &= !(1 << (idx % WORD_BITS));
+    }
+
+    fn clear(&mut self) {
+        for word in self.words.iter_mut() {
+            *word = 0;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    fn intersection_len(&self, other: &Bitset) -> usize {
+        self.words.iter().zip(other.words.iter())
+            .map(|(a, b)| (a & b).count_ones() as usize)
+            .sum()
+    }
+}
+
+/// The indices of the bits set in a word, given the index of its first bit.
+fn word_indices(offset: usize, mut word: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if word == 0 {
+            return None;
+        }
+        let bit = word.trailing_zeros() as usize;
+        word &= word - 1;
+        Some(offset + bit)
+    })
+}
+
+/// A range of values with the same interface as `RestrictedRange`, that
+/// keeps the accepted values as a bitset, with a counter per value of the
+/// rejections that apply to it and of the restrictions that allow it.
+/// Adding or removing a restriction then takes a few operations per word,
+/// plus moving whichever of the changed and unchanged values are fewer.
+/// Like rejections, restrictions can be removed in any order.
+///
+/// The values are also kept in a list with the rejected values in front of
+/// the accepted ones, so that both can be handed out as slices.
+#[derive(Debug, Clone)]
+pub struct BitsetRange<T> {
+    accepted: Bitset,
+    // values that no rejection applies to
+    unrejected: Bitset,
+    times_rejected: Table<T, usize>,
+    // a value is allowed by every restriction when this equals the number
+    // of restrictions
+    times_allowed: Table<T, usize>,
+    num_restrictions: usize,
+
+    values: Vec<Num<T>>,
+    positions: Table<T, usize>,
+    frontier: usize,
+
+    // scratch space for the values an operation is about
+    mask: Bitset,
+}
+
+impl<T> BitsetRange<T> {
+    pub fn new(value_count: Count<T>) -> Self {
+        let len = value_count.as_usize();
+        BitsetRange {
+            accepted: Bitset::full(len),
+            unrejected: Bitset::full(len),
+            times_rejected: value_count.map_nums(|_| 0),
+            times_allowed: value_count.map_nums(|_| 0),
+            num_restrictions: 0,
+            values: value_count.nums().collect(),
+            positions: value_count.map_nums(|value_num| value_num.as_usize()),
+            frontier: 0,
+            mask: Bitset::empty(len),
+        }
+    }
+
+    pub fn accepted(&self) -> &[Num<T>] {
+        &self.values[self.frontier..]
+    }
+
+    pub fn rejected(&self) -> &[Num<T>] {
+        &self.values[..self.frontier]
+    }
+
+    pub fn accepts(&self, value_num: Num<T>) -> bool {
+        self.accepted.contains(value_num.as_usize())
+    }
+
+    /// The number of accepted values.
+    pub fn len(&self) -> usize {
+        self.values.len() - self.frontier
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of values both ranges accept.
+    pub fn intersection_len(&self, other: &BitsetRange<T>) -> usize {
+        self.accepted.intersection_len(&other.accepted)
+    }
+
+    /// Whether any value is accepted by both ranges.
+    pub fn intersects(&self, other: &BitsetRange<T>) -> bool {
+        self.accepted.words.iter().zip(other.accepted.words.iter())
+            .any(|(a, b)| a & b != 0)
+    }
+
+    // returns the values that were rejected by this operation
+    pub fn add_rejection<'a>(&'a mut self, rejected: &[Num<T>])
+        -> &'a [Num<T>]
+    {
+        let prev_frontier = self.frontier;
+        for &value_num in rejected {
+            self.reject(value_num);
+        }
+        &self.values[prev_frontier..self.frontier]
+    }
+
+    // returns the values that are now allowed because of this operation
+    pub fn remove_rejection<'a>(&'a mut self, rejected: &[Num<T>])
+        -> &'a [Num<T>]
+    {
+        let prev_frontier = self.frontier;
+        for &value_num in rejected {
+            self.unreject(value_num);
+        }
+        &self.values[self.frontier..prev_frontier]
+    }
+
+    // returns the values that were rejected by this operation
+    pub fn add_restriction<'a>(&'a mut self, allowed: &[Num<T>])
+        -> &'a [Num<T>]
+    {
+        let prev_frontier = self.frontier;
+        self.mask.clear();
+        for &value_num in allowed {
+            // a value that is listed twice is still allowed once
+            if !self.mask.contains(value_num.as_usize()) {
+                self.mask.insert(value_num.as_usize());
+                self.times_allowed[value_num] += 1;
+            }
+        }
+        self.num_restrictions += 1;
+
+        let num_kept = self.accepted.intersection_len(&self.mask);
+        if num_kept < self.len() - num_kept {
+            // Few values stay accepted, e.g. when a value is assigned: move
+            // those to the end of the list, and the frontier up to them.
+            let mut end = self.values.len();
+            for &value_num in allowed {
+                if self.accepts(value_num) && self.positions[value_num] < end {
+                    end -= 1;
+                    self.swap(self.positions[value_num], end);
+                }
+            }
+            for (word, mask) in self.accepted.words.iter_mut().zip(self.mask.words.iter()) {
+                *word &= mask;
+            }
+            self.frontier = end;
+        } else {
+            for idx in 0..self.mask.words.len() {
+                let word = self.accepted.words[idx] & !self.mask.words[idx];
+                for value in word_indices(idx * WORD_BITS, word) {
+                    self.hide(to_num(value));
+                }
+            }
+        }
+        &self.values[prev_frontier..self.frontier]
+    }
+
+    // returns the values that are now allowed because of this operation
+    pub fn remove_restriction<'a>(&'a mut self, allowed: &[Num<T>])
+        -> &'a [Num<T>]
+    {
+        let prev_frontier = self.frontier;
+        self.mask.clear();
+        for &value_num in allowed {
+            if !self.mask.contains(value_num.as_usize()) {
+                self.mask.insert(value_num.as_usize());
+                self.times_allowed[value_num] -= 1;
+            }
+        }
+        self.num_restrictions -= 1;
+
+        // The values that are accepted from now on. Only those that this
+        // restriction did not allow can have been restricted by it alone.
+        for idx in 0..self.mask.words.len() {
+            let candidates = self.unrejected.words[idx] &
+                !self.accepted.words[idx] &
+                !self.mask.words[idx];
+            let mut word = self.accepted.words[idx];
+            for value in word_indices(idx * WORD_BITS, candidates) {
+                if self.times_allowed[to_num(value)] == self.num_restrictions {
+                    word |= 1 << (value % WORD_BITS);
+                }
+            }
+            self.mask.words[idx] = word;
+        }
+        let num_shown = self.mask.len() - self.len();
+
+        if prev_frontier - num_shown < num_shown {
+            // Few values stay rejected: move those to the start of the list,
+            // and the frontier down to them.
+            let mut start = 0;
+            for idx in 0..self.mask.words.len() {
+                let word = !self.mask.words[idx];
+                for value in word_indices(idx * WORD_BITS, word) {
+                    if value < self.values.len() {
+                        self.swap(self.positions[to_num(value)], start);
+                        start += 1;
+                    }
+                }
+            }
+            self.accepted.words.copy_from_slice(&self.mask.words);
+            self.frontier = start;
+        } else {
+            for idx in 0..self.mask.words.len() {
+                let word = self.mask.words[idx] & !self.accepted.words[idx];
+                for value in word_indices(idx * WORD_BITS, word) {
+                    self.show(to_num(value));
+                }
+            }
+        }
+        &self.values[self.frontier..prev_frontier]
+    }
+
+    pub fn reject(&mut self, value_num: Num<T>) {
+        if self.times_rejected[value_num] == 0 {
+            self.unrejected.remove(value_num.as_usize());
+            if self.accepts(value_num) {
+                self.hide(value_num);
+            }
+        }
+        self.times_rejected[value_num] += 1;
+    }
+
+    pub fn unreject(&mut self, value_num: Num<T>) {
+        self.times_rejected[value_num] -= 1;
+        if self.times_rejected[value_num] == 0 {
+            self.unrejected.insert(value_num.as_usize());
+            if self.times_allowed[value_num] == self.num_restrictions {
+                self.show(value_num);
+            }
+        }
+    }
+
+    // Move an accepted value behind the frontier.
+    fn hide(&mut self, value_num: Num<T>) {
+        self.accepted.remove(value_num.as_usize());
+        let frontier = self.frontier;
+        self.swap(self.positions[value_num], frontier);
+        self.frontier += 1;
+    }
+
+    // Move a rejected value in front of the frontier.
+    fn show(&mut self, value_num: Num<T>) {
+        self.accepted.insert(value_num.as_usize());
+        self.frontier -= 1;
+        let frontier = self.frontier;
+        self.swap(self.positions[value_num], frontier);
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        self.values.swap(a, b);
+        self.positions[self.values[a]] = a;
+        self.positions[self.values[b]] = b;
+    }
+}
+
+#[cfg(test)]
+impl<T> BitsetRange<T> {
+    pub fn times_rejected(&self, value_num: Num<T>) -> usize {
+        self.times_rejected[value_num]
+    }
+
+    pub fn times_allowed(&self, value_num: Num<T>) -> usize {
+        self.times_allowed[value_num]
+    }
+
+    /// Check that the list, the bitsets and the counters agree.
+    pub fn check_integrity(&self) {
+        for (pos, &value_num) in self.values.iter().enumerate() {
+            assert_eq!(self.positions[value_num], pos);
+            let idx = value_num.as_usize();
+            assert_eq!(self.unrejected.contains(idx), self.times_rejected[value_num] == 0);
+            let accepted = self.unrejected.contains(idx) &&
+                self.times_allowed[value_num] == self.num_restrictions;
+            assert_eq!(self.accepted.contains(idx), accepted);
+            assert_eq!(accepted, pos >= self.frontier);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use data::RestrictedRange;
+    use cat::internal::to_count;
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    const MAX_VALUES: usize = 140;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        AddRejection(Vec<bool>),
+        // removes one of the rejections that were added
+        RemoveRejection(usize),
+        AddRestriction(Vec<bool>),
+        // removes one of the restrictions that were added
+        RemoveRestriction(usize),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        let subset = || vec(any::<bool>(), MAX_VALUES);
+        prop_oneof![
+            subset().prop_map(Op::AddRejection),
+            any::<usize>().prop_map(Op::RemoveRejection),
+            subset().prop_map(Op::AddRestriction),
+            any::<usize>().prop_map(Op::RemoveRestriction),
+        ]
+    }
+
+    fn subset(included: &[bool], count: usize) -> Vec<Num<()>> {
+        (0..count).filter(|&i| included[i]).map(to_num).collect()
+    }
+
+    fn sorted(values: &[Num<()>]) -> Vec<Num<()>> {
+        let mut vec = values.to_vec();
+        vec.sort();
+        vec
+    }
+
+    fn check_same(bitset: &BitsetRange<()>, range: &RestrictedRange<()>) {
+        bitset.check_integrity();
+        assert_eq!(sorted(bitset.accepted()), sorted(range.accepted()));
+        assert_eq!(sorted(bitset.rejected()), sorted(range.rejected()));
+        assert_eq!(bitset.len(), range.accepted().len());
+        for value_num in to_count::<()>(bitset.values.len()).nums() {
+            assert_eq!(bitset.accepts(value_num), range.accepts(value_num));
+        }
+    }
+
+    proptest! {
+        // every operation changes the same values as it does for a
+        // RestrictedRange
+        #[test]
+        fn test_same_as_restricted(count in 1..MAX_VALUES, ops in vec(op(), 0..40)) {
+            let mut bitset = BitsetRange::new(to_count(count));
+            let mut range = RestrictedRange::new(to_count(count));
+            let mut rejections: Vec<Vec<Num<()>>> = Vec::new();
+            let mut restrictions: Vec<Vec<Num<()>>> = Vec::new();
+
+            for op in ops {
+                let (changed, expected) = match op {
+                    Op::AddRejection(included) => {
+                        let values = subset(&included, count);
+                        let changed = sorted(bitset.add_rejection(&values));
+                        let expected = sorted(range.add_rejection(&values));
+                        rejections.push(values);
+                        (changed, expected)
+                    }
+                    Op::RemoveRejection(idx) => {
+                        if rejections.is_empty() {
+                            continue;
+                        }
+                        let values = rejections.remove(idx % rejections.len());
+                        (sorted(bitset.remove_rejection(&values)),
+                         sorted(range.remove_rejection(&values)))
+                    }
+                    Op::AddRestriction(included) => {
+                        let values = subset(&included, count);
+                        let changed = sorted(bitset.add_restriction(&values));
+                        let expected = sorted(range.add_restriction(&values));
+                        restrictions.push(values);
+                        (changed, expected)
+                    }
+                    Op::RemoveRestriction(idx) => {
+                        if restrictions.is_empty() {
+                            continue;
+                        }
+                        let values = restrictions.remove(idx % restrictions.len());
+                        (sorted(bitset.remove_restriction(&values)),
+                         sorted(range.remove_restriction(&values)))
+                    }
+                };
+                assert_eq!(changed, expected);
+                check_same(&bitset, &range);
+
+                // and the counters follow the rejections and restrictions
+                // that are left
+                for value_num in to_count::<()>(count).nums() {
+                    let times = |sets: &[Vec<Num<()>>]| {
+                        sets.iter().filter(|values| values.contains(&value_num)).count()
+                    };
+                    assert_eq!(bitset.times_rejected(value_num), times(&rejections));
+                    assert_eq!(bitset.times_allowed(value_num), times(&restrictions));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicate_values() {
+        let mut range: BitsetRange<()> = BitsetRange::new(to_count(100));
+        let allowed: Vec<Num<()>> = vec![to_num(3), to_num(5), to_num(3)];
+        range.add_restriction(&[to_num(3), to_num(4)]);
+        assert_eq!(sorted(range.add_restriction(&allowed)), [to_num(4)]);
+        range.check_integrity();
+        assert_eq!(range.times_allowed(to_num(3)), 2);
+
+        assert_eq!(sorted(range.remove_restriction(&allowed)), [to_num(4)]);
+        range.check_integrity();
+        assert_eq!(range.times_allowed(to_num(3)), 1);
+        assert_eq!(range.len(), 2);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let mut a: BitsetRange<()> = BitsetRange::new(to_count(100));
+        let mut b: BitsetRange<()> = BitsetRange::new(to_count(100));
+        assert_eq!(a.intersection_len(&b), 100);
+
+        let evens: Vec<Num<()>> = (0..100).filter(|i| i % 2 == 0).map(to_num).collect();
+        let low: Vec<Num<()>> = (0..70).map(to_num).collect();
+        a.add_restriction(&evens);
+        b.add_rejection(&low);
+        assert_eq!(a.intersection_len(&b), 15);
+        assert!(a.intersects(&b));
+
+        b.add_restriction(&[to_num(99)]);
+        assert_eq!(b.len(), 1);
+        assert_eq!(a.intersection_len(&b), 0);
+        assert!(!a.intersects(&b));
+    }
+}
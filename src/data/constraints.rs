@@ -0,0 +1,123 @@
+use super::types::*;
+
+use cat::*;
+
+/// The constraints between pairs of keys. Only the pairs where the origin
+/// actually restricts the target are stored, per origin and ordered by
+/// target, so that memory and propagation grow with the number of
+/// constraints instead of with the number of key pairs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstraintTable {
+    rows: Table<Key, Vec<(Num<Key>, Restrictor)>>,
+    len: usize,
+}
+
+impl ConstraintTable {
+    /// A table without any constraints.
+    pub fn new(key_count: Count<Key>) -> Self {
+        ConstraintTable {
+            rows: key_count.map_nums(|_| Vec::new()),
+            len: 0,
+        }
+    }
+
+    /// The restrictor of origin on target; None when origin does not
+    /// constrain target.
+    pub fn get(&self, origin: Num<Key>, target: Num<Key>)
+        -> Option<&Restrictor>
+    {
+        let row = &self.rows[origin];
+        match row.binary_search_by_key(&target, |&(key_num, _)| key_num) {
+            Ok(idx) => Some(&row[idx].1),
+            Err(_) => None,
+        }
+    }
+
+    /// Set the restrictor of origin on target, replacing any earlier one. A
+    /// restrictor that restricts nothing removes the constraint.
+    pub fn set(&mut self, origin: Num<Key>, target: Num<Key>, restrictor: Restrictor) {
+        let trivial = restrictor.enumerate()
+            .all(|(_, restriction)| restriction.is_trivial());
+        let row = &mut self.rows[origin];
+        match row.binary_search_by_key(&target, |&(key_num, _)| key_num) {
+            Ok(idx) if trivial => {
+                row.remove(idx);
+                self.len -= 1;
+            }
+            Ok(idx) => row[idx].1 = restrictor,
+            Err(_) if trivial => (),
+            Err(idx) => {
+                row.insert(idx, (target, restrictor));
+                self.len += 1;
+            }
+        }
+    }
+
+    /// The keys that origin constrains, with their restrictors, ordered by
+    /// key.
+    pub fn targets(&self, origin: Num<Key>) -> &[(Num<Key>, Restrictor)] {
+        &self.rows[origin]
+    }
+
+    /// All constraints as (origin, target, restrictor), ordered by origin
+    /// and then by target.
+    pub fn iter<'a>(&'a self)
+        -> impl Iterator<Item = (Num<Key>, Num<Key>, &'a Restrictor)> + 'a
+    {
+        self.rows.enumerate().flat_map(|(origin, row)| {
+            row.iter().map(move |(target, restrictor)| (origin, *target, restrictor))
+        })
+    }
+
+    /// The number of constrained pairs of keys.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether assignment a leaves assignment b possible.
+    pub fn allows(&self, a: Assignment, b: Assignment) -> bool {
+        match self.get(a.key_num, b.key_num) {
+            Some(restrictor) => restrictor[a.value_num].allows(b.value_num),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cat::internal::{to_count, to_num};
+
+    #[test]
+    fn test_set() {
+        let (x, y, z) = (to_num(0), to_num(1), to_num(2));
+        let (a, b): (Num<Value>, Num<Value>) = (to_num(0), to_num(1));
+        let restrictor = |first, second| {
+            Table::from_vec(vec![first, second])
+        };
+        let trivial = || restrictor(Restriction::Not(vec![]), Restriction::Not(vec![]));
+
+        let mut table = ConstraintTable::new(to_count(3));
+        table.set(x, z, restrictor(Restriction::Not(vec![a]), Restriction::Not(vec![])));
+        table.set(x, y, restrictor(Restriction::Only(vec![b]), Restriction::Not(vec![])));
+        table.set(y, x, trivial());
+        assert_eq!(table.len(), 2);
+        assert!(table.get(y, x).is_none());
+        let targets: Vec<Num<Key>> = table.targets(x).iter().map(|&(t, _)| t).collect();
+        assert_eq!(targets, [y, z]);
+
+        let assignment = |key_num, value_num| Assignment { key_num, value_num };
+        assert!(!table.allows(assignment(x, a), assignment(y, a)));
+        assert!(table.allows(assignment(x, b), assignment(y, a)));
+        assert!(table.allows(assignment(y, a), assignment(x, a)));
+
+        table.set(x, y, trivial());
+        assert_eq!(table.len(), 1);
+        let pairs: Vec<_> = table.iter().map(|(o, t, _)| (o, t)).collect();
+        assert_eq!(pairs, [(x, z)]);
+    }
+}
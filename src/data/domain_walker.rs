@@ -1,9 +1,17 @@
-use super::RestrictedRange;
+use super::{RangeKind, ValueRange};
 use super::types::*;
+use super::nogoods::{Nogood, NogoodStore};
+use super::all_different::{self, Filtering, HallSet};
 
 use cat::*;
 use cat::ops::*;
 
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+
+// For each key and neighbour, a support range number per value of the key.
+type Supports = Table<Key, Vec<Table<Value, usize>>>;
+
 pub struct DomainWalker<'d> {
     domain: &'d Domain,
 
@@ -11,14 +19,52 @@ pub struct DomainWalker<'d> {
     mapping: Table<Key, Option<Num<Value>>>,
 
     // Value domains remaining for each key
-    ranges: Table<Key, RestrictedRange<Value>>,
+    ranges: Table<Key, ValueRange<Value>>,
+
+    // Keys that share a constraint, in either direction
+    neighbours: Table<Key, Vec<Num<Key>>>,
+
+    // For each key and neighbour, in the order of neighbours: the values of
+    // the neighbour compatible with each value of the key, as an index into
+    // support_ranges. Pairs of keys that are constrained alike share their
+    // ranges.
+    supports: Supports,
+    support_ranges: Vec<ValueRange<Value>>,
+
+    // The all-different groups each key belongs to
+    groups: Table<Key, Vec<usize>>,
+
+    // The cardinality constraints on each value
+    cardinality: Table<Value, Vec<usize>>,
+
+    // Values rejected by propagation, in the order they were rejected
+    trail: Vec<Assignment>,
+
+    // Values rejected by assignments since the last propagation
+    assigned: Vec<Assignment>,
+
+    // For each rejected value, the assigned keys that caused its rejection.
+    // Only meaningful for values that are currently rejected.
+    reasons: Table<Key, Table<Value, Vec<Num<Key>>>>,
+
+    // Learned combinations of assignments that lead to no solution
+    nogoods: NogoodStore,
 }
 
+/// A position in the propagation trail of a walker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
 impl<'d> DomainWalker<'d> {
     pub fn new(domain: &'d Domain) -> Self {
+        DomainWalker::with_range_kind(domain, RangeKind::for_count(domain.values.count()))
+    }
+
+    /// A walker that keeps the value ranges in the given representation.
+    pub fn with_range_kind(domain: &'d Domain, kind: RangeKind) -> Self {
         // init domains
         let ranges = domain.key_restrictions.map(|restriction| {
-            let mut range = RestrictedRange::new(domain.values.count());
+            let mut range = ValueRange::new(kind, domain.values.count());
             match restriction {
                 Restriction::Not(ref values) => {
                     range.add_rejection(values);
@@ -27,41 +73,111 @@ impl<'d> DomainWalker<'d> {
                     range.add_restriction(values);
                 }
             }
-            return range;
+            range
+        });
+
+        let mut neighbours: Table<Key, Vec<Num<Key>>> = domain.keys.map_nums(|_| Vec::new());
+        for (origin, target, _) in domain.constraint_table.iter() {
+            if origin != target {
+                neighbours[origin].push(target);
+                neighbours[target].push(origin);
+            }
+        }
+        neighbours.map_mut(|keys| {
+            keys.sort();
+            keys.dedup();
+        });
+
+        let (supports, support_ranges) = support_ranges(domain, kind, &neighbours);
+
+        let groups = domain.keys.map_nums(|key_num| {
+            domain.all_different.iter().enumerate()
+                .filter(|&(_, group)| group.contains(&key_num))
+                .map(|(idx, _)| idx)
+                .collect()
+        });
+
+        let cardinality = domain.values.map_nums(|value_num| {
+            domain.cardinality.iter().enumerate()
+                .filter(|&(_, c)| c.value == value_num)
+                .map(|(idx, _)| idx)
+                .collect()
         });
 
         DomainWalker {
             mapping: domain.keys.map_nums(|_| None),
             ranges,
+            neighbours,
+            supports,
+            support_ranges,
+            groups,
+            cardinality,
+            trail: Vec::new(),
+            assigned: Vec::new(),
+            reasons: domain.keys.map_nums(|_| {
+                domain.values.map_nums(|_| Vec::new())
+            }),
+            nogoods: NogoodStore::new(domain.keys.count()),
             domain,
         }
     }
 
     /// Get current walker position.
-    pub fn mapping<'a>(&'a self) -> &'a Table<Key, Option<Num<Value>>> {
+    pub fn mapping(&self) -> &Table<Key, Option<Num<Value>>> {
         &self.mapping
     }
 
     /// Get values that can be assigned to key_num without causing
     /// inconsistencies.
-    pub fn range_for<'a>(&'a self, key_num: Num<Key>) -> &'a [Num<Value>] {
+    pub fn range_for(&self, key_num: Num<Key>) -> &[Num<Value>] {
         self.ranges[key_num].accepted()
     }
 
+    /// Keys that share a constraint with given key, in either direction.
+    pub fn neighbours(&self, key_num: Num<Key>) -> &[Num<Key>] {
+        &self.neighbours[key_num]
+    }
+
+    pub fn domain(&self) -> &'d Domain {
+        self.domain
+    }
+
+    /// The number of values left for other that are compatible with the
+    /// given assignment. Other has to be a neighbour of the assigned key.
+    pub fn num_supports(&self, a: Assignment, other: Num<Key>) -> usize {
+        self.support(a, other).intersection_len(&self.ranges[other])
+    }
+
+    // the values of other compatible with the given assignment
+    fn support(&self, a: Assignment, other: Num<Key>) -> &ValueRange<Value> {
+        &self.support_ranges[self.supports(a.key_num, other)[a.value_num]]
+    }
+
+    // the support range numbers for the values of key_num in other
+    fn supports(&self, key_num: Num<Key>, other: Num<Key>) -> &Table<Value, usize> {
+        let idx = self.neighbours[key_num].binary_search(&other).unwrap();
+        &self.supports[key_num][idx]
+    }
+
     /// Assign a value to a key.
     pub fn assign(&mut self, key_num: Num<Key>, value_num: Num<Value>) {
-        println!("assigning {:?} at {:?}", self.domain.values[value_num], self.domain.keys[key_num]);
-        
-        self.ranges[key_num].add_restriction(&vec![value_num]);
+        let rejected = self.ranges[key_num].add_restriction(&[value_num]).to_vec();
+        self.set_reasons(key_num, &rejected, &[key_num]);
+        self.add_assigned(key_num, &rejected);
 
         self.mapping[key_num] = Some(value_num);
-        let row = &self.domain.constraint_table[key_num];
-
-        for target_num in self.domain.keys.nums() {
-            let restriction = &row[target_num][value_num];
-            self.restrict(target_num, restriction);
+        let domain = self.domain;
+        for (target_num, restrictor) in domain.constraint_table.targets(key_num) {
+            let rejected = self.restrict(*target_num, &restrictor[value_num]);
+            self.set_reasons(*target_num, &rejected, &[key_num]);
+            self.add_assigned(*target_num, &rejected);
         }
+    }
 
+    fn add_assigned(&mut self, key_num: Num<Key>, values: &[Num<Value>]) {
+        self.assigned.extend(values.iter().map(|&value_num| {
+            Assignment { key_num, value_num }
+        }));
     }
 
     /// Clear the value for a key.
@@ -73,14 +189,13 @@ impl<'d> DomainWalker<'d> {
 
         {
             self.ranges[key_num]
-                .remove_restriction(&vec![value_num]);
+                .remove_restriction(&[value_num]);
 
         }
 
-        let row = &self.domain.constraint_table[key_num];
-        for target_num in self.domain.keys.nums() {
-            let restriction = &row[target_num][value_num];
-            self.unrestrict(target_num, restriction);
+        let domain = self.domain;
+        for (target_num, restrictor) in domain.constraint_table.targets(key_num) {
+            self.unrestrict(*target_num, &restrictor[value_num]);
         }
     }
 
@@ -91,36 +206,498 @@ impl<'d> DomainWalker<'d> {
                 Some(value_num) => Assignment { key_num, value_num },
             };
 
-            if assignments_conflict(&self.domain, assignment, a) {
+            if assignments_conflict(self.domain, assignment, a) {
                 self.unassign(a.key_num);
             }
         }
     }
 
-    fn restrict(&mut self, key_num: Num<Key>, restriction: &Restriction) {
-        match restriction {
-            &Restriction::Not(ref values) => {
+    // returns the values that were rejected by this operation
+    fn restrict(&mut self, key_num: Num<Key>, restriction: &Restriction)
+        -> Vec<Num<Value>>
+    {
+        let rejected = match *restriction {
+            Restriction::Not(ref values) => {
                 self.ranges[key_num].add_rejection(values)
             }
-            &Restriction::Only(ref values) => {
+            Restriction::Only(ref values) => {
                 self.ranges[key_num].add_restriction(values)
             }
         };
+        rejected.to_vec()
+    }
 
+    fn set_reasons(&mut self,
+                   key_num: Num<Key>,
+                   values: &[Num<Value>],
+                   reason: &[Num<Key>])
+    {
+        for &value_num in values {
+            let entry = &mut self.reasons[key_num][value_num];
+            entry.clear();
+            entry.extend_from_slice(reason);
+        }
+    }
+
+    /// The assigned keys that caused value_num to be rejected for key_num.
+    pub fn reason(&self, key_num: Num<Key>, value_num: Num<Value>)
+        -> &[Num<Key>]
+    {
+        &self.reasons[key_num][value_num]
+    }
+
+    /// Explain why the walker is in an invalid state: returns a set of
+    /// assigned keys whose assignments together cause a range to be wiped
+    /// out. Returns None when the state is valid.
+    pub fn conflict(&self) -> Option<Vec<Num<Key>>> {
+        let key_num = self.wipeout()?;
+        Some(self.explain_range(key_num))
+    }
+
+    /// A key whose range was wiped out, if any.
+    pub fn wipeout(&self) -> Option<Num<Key>> {
+        self.domain.keys.nums().find(|&key_num| {
+            self.ranges[key_num].accepted().is_empty()
+        })
+    }
+
+    /// The assigned keys that caused values to be rejected for key_num.
+    pub fn explain_range(&self, key_num: Num<Key>) -> Vec<Num<Key>> {
+        let mut keys = Vec::new();
+        for &value_num in self.ranges[key_num].rejected() {
+            union(&mut keys, self.reason(key_num, value_num));
+        }
+        keys
     }
 
     fn unrestrict(&mut self, key_num: Num<Key>, restriction: &Restriction) {
-        match restriction {
-            &Restriction::Not(ref values) => {
+        match *restriction {
+            Restriction::Not(ref values) => {
                 self.ranges[key_num].remove_rejection(values)
             }
-            &Restriction::Only(ref values) => {
+            Restriction::Only(ref values) => {
                 self.ranges[key_num].remove_restriction(values)
             }
         };
 
     }
 
+    /// Current position in the propagation trail.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.trail.len())
+    }
+
+    /// Undo all propagation that happened after the given checkpoint.
+    /// Checkpoints have to be restored in reverse order of creation, and
+    /// before undoing the assignments that preceded them.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        let Checkpoint(len) = checkpoint;
+        while self.trail.len() > len {
+            let a = self.trail.pop().unwrap();
+            self.ranges[a.key_num].remove_rejection(&[a.value_num]);
+        }
+    }
+
+    /// Make all key ranges arc consistent, filter the all-different groups
+    /// and reject the values that would complete a nogood. Returns false
+    /// when a range was wiped out; the walker is then in an invalid state.
+    pub fn propagate(&mut self) -> bool {
+        self.assigned.clear();
+        let nogoods = (0..self.nogoods.len()).collect();
+        if self.propagate_nogoods(nogoods, None).is_none() {
+            return false;
+        }
+
+        let mut arcs = VecDeque::new();
+        for key_num in self.domain.keys.nums() {
+            for &other in self.neighbours[key_num].iter() {
+                arcs.push_back((key_num, other));
+            }
+        }
+        let groups = (0..self.domain.all_different.len()).collect();
+        let cardinality = (0..self.domain.cardinality.len()).collect();
+        self.propagate_constraints(arcs, groups, cardinality)
+    }
+
+    /// Restore consistency after the given key was assigned. Returns false
+    /// when a range was wiped out.
+    pub fn propagate_from(&mut self, key_num: Num<Key>) -> bool {
+        let mut cardinality = Vec::new();
+        for a in mem::take(&mut self.assigned) {
+            self.add_cardinality(&mut cardinality, a);
+        }
+
+        let mark = self.trail.len();
+        let nogoods = self.nogoods.involving(key_num).to_vec();
+        let narrowed = match self.propagate_nogoods(nogoods, Some(key_num)) {
+            Some(narrowed) => narrowed,
+            None => return false,
+        };
+
+        // Assigning a key narrows its own range and, through its
+        // constraints, the ranges of its neighbours.
+        let mut arcs = VecDeque::new();
+        for &neighbour in self.neighbours[key_num].iter() {
+            arcs.push_back((neighbour, key_num));
+            for &other in self.neighbours[neighbour].iter() {
+                if other != key_num {
+                    arcs.push_back((other, neighbour));
+                }
+            }
+        }
+        for &narrowed_key in narrowed.iter() {
+            for &neighbour in self.neighbours[narrowed_key].iter() {
+                arcs.push_back((neighbour, narrowed_key));
+            }
+        }
+
+        let mut groups = Vec::new();
+        for &changed in [key_num].iter().chain(self.neighbours[key_num].iter()) {
+            self.add_groups(&mut groups, changed);
+        }
+        for &narrowed_key in narrowed.iter() {
+            self.add_groups(&mut groups, narrowed_key);
+        }
+        for idx in mark..self.trail.len() {
+            let a = self.trail[idx];
+            self.add_cardinality(&mut cardinality, a);
+        }
+        self.propagate_constraints(arcs, groups, cardinality)
+    }
+
+    /// Alternate between revising arcs, filtering all-different groups and
+    /// filtering cardinality constraints, until none narrows a range anymore.
+    /// Returns false on a wipeout.
+    fn propagate_constraints(&mut self,
+                             mut arcs: VecDeque<(Num<Key>, Num<Key>)>,
+                             mut groups: Vec<usize>,
+                             mut cardinality: Vec<usize>)
+                             -> bool
+    {
+        loop {
+            let mark = self.trail.len();
+            if !self.propagate_arcs(arcs) {
+                return false;
+            }
+            for idx in mark..self.trail.len() {
+                let a = self.trail[idx];
+                self.add_groups(&mut groups, a.key_num);
+                self.add_cardinality(&mut cardinality, a);
+            }
+
+            let mark = self.trail.len();
+            for group in groups.drain(..) {
+                if !self.filter_all_different(group) {
+                    return false;
+                }
+            }
+            for idx in mark..self.trail.len() {
+                let a = self.trail[idx];
+                self.add_cardinality(&mut cardinality, a);
+            }
+            if !self.filter_cardinality(&mut cardinality) {
+                return false;
+            }
+
+            // the ranges narrowed by the groups affect their neighbours,
+            // and the other groups they are in.
+            arcs = VecDeque::new();
+            for idx in mark..self.trail.len() {
+                let key_num = self.trail[idx].key_num;
+                for &neighbour in self.neighbours[key_num].iter() {
+                    arcs.push_back((neighbour, key_num));
+                }
+                self.add_groups(&mut groups, key_num);
+            }
+
+            if arcs.is_empty() && groups.is_empty() {
+                return true;
+            }
+        }
+    }
+
+    fn add_groups(&self, groups: &mut Vec<usize>, key_num: Num<Key>) {
+        for &group in self.groups[key_num].iter() {
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+    }
+
+    /// Add the cardinality constraints affected by a rejection: those on the
+    /// rejected value, and those on the value the key is left with, if any.
+    fn add_cardinality(&self, constraints: &mut Vec<usize>, a: Assignment) {
+        let accepted = self.ranges[a.key_num].accepted();
+        let fixed = if accepted.len() == 1 { Some(accepted[0]) } else { None };
+        for value_num in Some(a.value_num).into_iter().chain(fixed) {
+            for &idx in self.cardinality[value_num].iter() {
+                if !constraints.contains(&idx) {
+                    constraints.push(idx);
+                }
+            }
+        }
+    }
+
+    /// Reject the values that cannot be part of an assignment of distinct
+    /// values to the keys of a group. Returns false when there is no such
+    /// assignment; one of the ranges is wiped out then.
+    fn filter_all_different(&mut self, group: usize) -> bool {
+        let domain = self.domain;
+        let filtering = {
+            let ranges = &self.ranges;
+            let keys = &domain.all_different[group];
+            let capacity = |value_num| domain.group_capacity(value_num);
+            all_different::filter(keys, capacity, |key_num| {
+                ranges[key_num].accepted()
+            })
+        };
+
+        match filtering {
+            Filtering::Infeasible(hall_set) => {
+                let reason = self.explain_hall_set(&hall_set);
+                let key_num = hall_set.keys[0];
+                for value_num in self.ranges[key_num].accepted().to_vec() {
+                    self.reject(Assignment { key_num, value_num }, reason.clone());
+                }
+                false
+            }
+            Filtering::Consistent { hall_sets, pruned } => {
+                let reasons: Vec<Vec<Num<Key>>> = hall_sets.iter()
+                    .map(|hall_set| self.explain_hall_set(hall_set))
+                    .collect();
+                for (a, idx) in pruned {
+                    self.reject(a, reasons[idx].clone());
+                }
+                true
+            }
+        }
+    }
+
+    /// The assigned keys that confined the keys of a Hall set to its
+    /// values.
+    fn explain_hall_set(&self, hall_set: &HallSet) -> Vec<Num<Key>> {
+        let mut keys = Vec::new();
+        for &key_num in hall_set.keys.iter() {
+            for &value_num in self.ranges[key_num].rejected() {
+                if !hall_set.values.contains(&value_num) {
+                    union(&mut keys, self.reason(key_num, value_num));
+                }
+            }
+        }
+        keys
+    }
+
+    /// Filter the given cardinality constraints, and those affected by the
+    /// values this rejects, until none is left. Returns false when the
+    /// bounds of one can not be met; a range is wiped out then.
+    fn filter_cardinality(&mut self, constraints: &mut Vec<usize>) -> bool {
+        while let Some(idx) = constraints.pop() {
+            let mark = self.trail.len();
+            if !self.filter_cardinality_bounds(idx) {
+                return false;
+            }
+            for idx in mark..self.trail.len() {
+                let a = self.trail[idx];
+                self.add_cardinality(constraints, a);
+            }
+        }
+        true
+    }
+
+    /// Keep the number of keys that take a value within its bounds.
+    /// When just enough keys can take the value to reach its minimum, they
+    /// all have to take it; when enough keys took the value to reach its
+    /// maximum, the other keys cannot take it anymore. Returns false when the
+    /// bounds can not be met; a range is wiped out then.
+    fn filter_cardinality_bounds(&mut self, idx: usize) -> bool {
+        let domain = self.domain;
+        let c = &domain.cardinality[idx];
+        let value_num = c.value;
+        let possible: Vec<Num<Key>> = domain.keys.nums()
+            .filter(|&key_num| self.ranges[key_num].accepts(value_num))
+            .collect();
+        let fixed: Vec<Num<Key>> = possible.iter().cloned()
+            .filter(|&key_num| self.ranges[key_num].accepted().len() == 1)
+            .collect();
+
+        if possible.len() < c.min {
+            let reason = self.explain_unsupported(value_num, &possible);
+            let key_num = match domain.keys.nums().next() {
+                Some(key_num) => key_num,
+                None => return false,
+            };
+            for other in self.ranges[key_num].accepted().to_vec() {
+                self.reject(Assignment { key_num, value_num: other },
+                            reason.clone());
+            }
+            return false;
+        }
+
+        if possible.len() == c.min && fixed.len() < possible.len() {
+            let reason = self.explain_unsupported(value_num, &possible);
+            for &key_num in possible.iter() {
+                let others: Vec<Num<Value>> = self.ranges[key_num]
+                    .accepted().iter().cloned()
+                    .filter(|&other| other != value_num)
+                    .collect();
+                for other in others {
+                    self.reject(Assignment { key_num, value_num: other },
+                                reason.clone());
+                }
+            }
+            return true;
+        }
+
+        let max = match c.max {
+            Some(max) if fixed.len() >= max => max,
+            _ => return true,
+        };
+        let mut reason = Vec::new();
+        for &key_num in fixed.iter() {
+            union(&mut reason, &self.explain_range(key_num));
+        }
+        if fixed.len() > max {
+            self.reject(Assignment { key_num: fixed[0], value_num }, reason);
+            return false;
+        }
+        for &key_num in possible.iter().filter(|k| !fixed.contains(k)) {
+            self.reject(Assignment { key_num, value_num }, reason.clone());
+        }
+        true
+    }
+
+    /// The assigned keys that rejected value_num for all keys but the given
+    /// ones.
+    fn explain_unsupported(&self, value_num: Num<Value>, except: &[Num<Key>])
+        -> Vec<Num<Key>>
+    {
+        let mut keys = Vec::new();
+        for key_num in self.domain.keys.nums() {
+            if !except.contains(&key_num) {
+                union(&mut keys, self.reason(key_num, value_num));
+            }
+        }
+        keys
+    }
+
+    /// Add a nogood, which will be taken into account from the next
+    /// propagation on.
+    pub fn add_nogood(&mut self, nogood: Nogood) -> bool {
+        self.nogoods.insert(nogood)
+    }
+
+    pub fn nogoods(&self) -> &NogoodStore {
+        &self.nogoods
+    }
+
+    /// Reject the values that would complete one of the given nogoods.
+    /// When a nogood is already complete, the value of `complete_key` (or
+    /// any of its keys) is rejected, which wipes out its range.
+    /// Returns the keys whose range was narrowed, or None on a wipeout.
+    fn propagate_nogoods(&mut self,
+                         nogoods: Vec<usize>,
+                         complete_key: Option<Num<Key>>)
+                         -> Option<Vec<Num<Key>>>
+    {
+        let mut narrowed = Vec::new();
+        for idx in nogoods {
+            let rejection = unit_rejection(
+                &self.mapping,
+                &self.nogoods.nogoods()[idx],
+                complete_key
+            );
+
+            let a = match rejection {
+                Some(a) => a,
+                None => continue,
+            };
+
+            if !self.ranges[a.key_num].accepts(a.value_num) {
+                continue;
+            }
+
+            let reason = self.nogoods.nogoods()[idx].assignments().iter()
+                .map(|other| other.key_num)
+                .filter(|&key_num| key_num != a.key_num)
+                .collect();
+            self.reject(a, reason);
+
+            if self.ranges[a.key_num].accepted().is_empty() {
+                return None;
+            }
+            if !narrowed.contains(&a.key_num) {
+                narrowed.push(a.key_num);
+            }
+        }
+        Some(narrowed)
+    }
+
+    /// AC-3: revise arcs until no range changes anymore.
+    fn propagate_arcs(&mut self, mut arcs: VecDeque<(Num<Key>, Num<Key>)>)
+        -> bool
+    {
+        let mut queued: HashSet<_> = arcs.iter().cloned().collect();
+
+        while let Some((key_num, other)) = arcs.pop_front() {
+            queued.remove(&(key_num, other));
+
+            if !self.revise(key_num, other) {
+                continue;
+            }
+
+            if self.ranges[key_num].accepted().is_empty() {
+                return false;
+            }
+
+            // supports for the neighbours of key_num might have disappeared
+            for &neighbour in self.neighbours[key_num].iter() {
+                let arc = (neighbour, key_num);
+                if neighbour != other && queued.insert(arc) {
+                    arcs.push_back(arc);
+                }
+            }
+        }
+        true
+    }
+
+    /// Reject the values for key_num that have no support in the range of
+    /// other. Returns whether any values were rejected.
+    fn revise(&mut self, key_num: Num<Key>, other: Num<Key>) -> bool {
+        let unsupported: Vec<(Num<Value>, Vec<Num<Key>>)> = {
+            let supports = self.supports(key_num, other);
+            let other_range = &self.ranges[other];
+            self.ranges[key_num].accepted().iter().cloned().filter_map(|value_num| {
+                let support = &self.support_ranges[supports[value_num]];
+                if support.intersects(other_range) {
+                    return None;
+                }
+                // value_num lost its support because of the rejection of
+                // the values of other it was compatible with.
+                let mut reason = Vec::new();
+                for &other_value in other_range.rejected() {
+                    if support.accepts(other_value) {
+                        union(&mut reason, self.reason(other, other_value));
+                    }
+                }
+                Some((value_num, reason))
+            }).collect()
+        };
+
+        let revised = !unsupported.is_empty();
+        for (value_num, reason) in unsupported {
+            self.reject(Assignment { key_num, value_num }, reason);
+        }
+        revised
+    }
+
+    /// Reject an accepted value, remembering the reason for undoing and
+    /// explaining conflicts.
+    fn reject(&mut self, a: Assignment, reason: Vec<Num<Key>>) {
+        self.ranges[a.key_num].add_rejection(&[a.value_num]);
+        self.reasons[a.key_num][a.value_num] = reason;
+        self.trail.push(a);
+    }
+
     pub fn valid_state(&self) -> bool {
         self.mapping.enumerate().all(|(key_num, value)| {
             let valid = match value {
@@ -130,13 +707,98 @@ impl<'d> DomainWalker<'d> {
                 }
             };
 
-            let satisfiable = self.ranges[key_num].accepted().len() > 0;
+            let satisfiable = !self.ranges[key_num].accepted().is_empty();
 
             valid && satisfiable
         })
     }
 }
 
+/// For each key and neighbour, the values of the neighbour compatible with
+/// each value of the key, as indices into a list of ranges. Pairs that allow
+/// the same values share a range.
+fn support_ranges(domain: &Domain,
+                  kind: RangeKind,
+                  neighbours: &Table<Key, Vec<Num<Key>>>)
+                  -> (Supports, Vec<ValueRange<Value>>)
+{
+    let mut supports = neighbours.map(|keys| Vec::with_capacity(keys.len()));
+    let mut ranges = Vec::new();
+    let mut range_nums: HashMap<Restriction, usize> = HashMap::new();
+    let trivial = Restriction::Not(Vec::new());
+    for (key_num, keys) in neighbours.enumerate() {
+        for &other in keys.iter() {
+            let forward = domain.constraint_table.get(key_num, other);
+            // the values of other that allow each value of key_num
+            let backward = domain.constraint_table.get(other, key_num).map(|restrictor| {
+                let constraint = Constraint {
+                    origin: other,
+                    target: key_num,
+                    restrictor: restrictor.clone(),
+                };
+                constraint.inverse().restrictor
+            });
+            let table = domain.values.map_nums(|value_num| {
+                let forward = forward.map_or(&trivial, |r| &r[value_num]);
+                let support = match backward {
+                    Some(ref backward) => forward.intersect(&backward[value_num]),
+                    None => forward.clone(),
+                };
+                *range_nums.entry(support).or_insert_with_key(|support| {
+                    let mut range = ValueRange::new(kind, domain.values.count());
+                    match *support {
+                        Restriction::Not(ref values) => range.add_rejection(values),
+                        Restriction::Only(ref values) => range.add_restriction(values),
+                    };
+                    ranges.push(range);
+                    ranges.len() - 1
+                })
+            });
+            supports[key_num].push(table);
+        }
+    }
+    (supports, ranges)
+}
+
+/// The assignment that has to be ruled out to keep the mapping from
+/// completing the nogood, if any.
+fn unit_rejection(mapping: &Table<Key, Option<Num<Value>>>,
+                  nogood: &Nogood,
+                  complete_key: Option<Num<Key>>)
+                  -> Option<Assignment>
+{
+    let mut open = None;
+    for &a in nogood.assignments() {
+        match mapping[a.key_num] {
+            Some(value_num) if value_num == a.value_num => (),
+            // this assignment can no longer be completed
+            Some(_) => return None,
+            // more than one assignment left to make
+            None if open.is_some() => return None,
+            None => open = Some(a),
+        }
+    }
+
+    if open.is_some() {
+        return open;
+    }
+
+    // The nogood is complete; reject one of its assignments
+    let assignments = nogood.assignments();
+    assignments.iter().cloned()
+        .find(|a| Some(a.key_num) == complete_key)
+        .or_else(|| assignments.first().cloned())
+}
+
+/// Add the keys in `keys` that are not in `set` yet.
+fn union(set: &mut Vec<Num<Key>>, keys: &[Num<Key>]) {
+    for &key_num in keys {
+        if !set.contains(&key_num) {
+            set.push(key_num);
+        }
+    }
+}
+
 /// Whether given assignments conflict in the stated domain.
 fn assignments_conflict(domain: &Domain, a: Assignment, b: Assignment) -> bool
 {
@@ -145,8 +807,11 @@ fn assignments_conflict(domain: &Domain, a: Assignment, b: Assignment) -> bool
 
 /// Whether assignment a prohibits assignment b from being made.
 fn assignment_prohibits(domain: &Domain, a: Assignment, b: Assignment) -> bool {
-    let cs = &domain.constraint_table;    
-    match cs[a.key_num][b.key_num][a.value_num] {
+    let restrictor = match domain.constraint_table.get(a.key_num, b.key_num) {
+        Some(restrictor) => restrictor,
+        None => return false,
+    };
+    match restrictor[a.value_num] {
         Restriction::Not(ref values) => {
             !values.contains(&b.value_num)
         }
@@ -168,6 +833,8 @@ mod test {
     use super::*;
     use failure::ResultExt;
     use json;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
 
     enum Op {
         Assign(Assignment),
@@ -187,6 +854,134 @@ mod test {
     fn test_domains() {
         let domain = json::read_config("abcABC.json")
             .context("Could not parse domain").unwrap();
+        let w = DomainWalker::new(&domain);
+        assert!(w.valid_state());
         // TODO
     }
+
+    fn key(domain: &Domain, name: &str) -> Num<Key> {
+        domain.keys.nums().find(|&k| domain.keys[k] == name).unwrap()
+    }
+
+    fn value(domain: &Domain, name: &str) -> Num<Value> {
+        domain.values.nums().find(|&v| domain.values[v] == name).unwrap()
+    }
+
+    fn range_names(w: &DomainWalker, domain: &Domain, key_name: &str)
+        -> Vec<String>
+    {
+        let mut names: Vec<String> = w.range_for(key(domain, key_name))
+            .iter()
+            .map(|&v| domain.values[v].clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_propagate() {
+        let domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+        for &kind in [RangeKind::Permutation, RangeKind::Bitset].iter() {
+            let mut w = DomainWalker::with_range_kind(&domain, kind);
+
+            // the upper keys can only hold what the lower keys map them to
+            assert!(w.propagate());
+            assert_eq!(range_names(&w, &domain, "2/upper"), ["A", "B", "C"]);
+
+            // a on 1/lower leaves A for 1/upper, and b and c for 2/lower
+            let (k, v) = (key(&domain, "1/lower"), value(&domain, "a"));
+            let a = Assignment { key_num: k, value_num: v };
+            assert_eq!(w.num_supports(a, key(&domain, "1/upper")), 1);
+            assert_eq!(w.num_supports(a, key(&domain, "2/lower")), 2);
+
+            let checkpoint = w.checkpoint();
+            Op::Assign(Assignment { key_num: k, value_num: v }).apply(&mut w);
+            assert!(w.propagate_from(k));
+
+            assert_eq!(range_names(&w, &domain, "1/upper"), ["A"]);
+            assert_eq!(range_names(&w, &domain, "2/lower"), ["b", "c"]);
+            assert_eq!(range_names(&w, &domain, "2/upper"), ["B", "C"]);
+            assert!(w.valid_state());
+
+            w.restore(checkpoint);
+            w.unassign(k);
+            assert_eq!(range_names(&w, &domain, "1/lower"), ["a", "b", "c"]);
+            assert_eq!(range_names(&w, &domain, "1/upper"), ["A", "B", "C"]);
+            assert_eq!(range_names(&w, &domain, "2/upper"), ["A", "B", "C"]);
+        }
+    }
+
+    #[test]
+    fn test_propagate_cardinality() {
+        let mut domain = json::read_config("petersen.json")
+            .context("Could not parse domain").unwrap();
+        let red = value(&domain, "red");
+        domain.cardinality.push(Cardinality { value: red, min: 0, max: Some(1) });
+        let mut w = DomainWalker::new(&domain);
+        assert!(w.propagate());
+
+        // red on outer/1 rules out red for all other keys, adjacent or not
+        let k = key(&domain, "outer/1");
+        w.assign(k, red);
+        assert!(w.propagate_from(k));
+        assert_eq!(range_names(&w, &domain, "outer/3"), ["blue", "green"]);
+    }
+
+    #[test]
+    fn test_propagate_fixpoint() {
+        let mut rng = StdRng::seed_from_u64(3000);
+        for _ in 0..200 {
+            let mut domain = json::read_config("petersen.json")
+                .context("Could not parse domain").unwrap();
+            // a colouring takes each colour 3 or 4 times
+            for value_num in domain.values.nums() {
+                let max = rng.gen_range(3, 5);
+                let min = max - rng.gen_range(0, 2);
+                domain.cardinality.push(Cardinality { value: value_num, min, max: Some(max) });
+            }
+
+            // after each assignment, propagating over all arcs, groups and
+            // bounds rejects nothing more than propagating from the key
+            let mut w = DomainWalker::new(&domain);
+            let mut propagated = w.propagate();
+            for key_num in domain.keys.nums() {
+                if !propagated {
+                    break;
+                }
+                let range = w.range_for(key_num);
+                let value_num = range[rng.gen_range(0, range.len())];
+                w.assign(key_num, value_num);
+                propagated = w.propagate_from(key_num);
+                if propagated {
+                    let checkpoint = w.checkpoint();
+                    assert!(w.propagate());
+                    assert_eq!(w.checkpoint(), checkpoint);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_propagate_wipeout() {
+        let domain = json::read_config("petersen.json")
+            .context("Could not parse domain").unwrap();
+        let mut w = DomainWalker::new(&domain);
+        assert!(w.propagate());
+
+        // colour both endpoints of inner/1 - inner/3 red
+        let red = value(&domain, "red");
+        assert_eq!(w.conflict(), None);
+        for name in ["outer/2", "inner/1", "inner/3"].iter() {
+            let k = key(&domain, name);
+            Op::Assign(Assignment { key_num: k, value_num: red }).apply(&mut w);
+            w.propagate_from(k);
+        }
+        assert!(!w.valid_state());
+
+        // outer/2 is not adjacent to either, so it is not to blame
+        let mut conflict = w.conflict().unwrap();
+        conflict.sort();
+        assert_eq!(conflict, [key(&domain, "inner/1"), key(&domain, "inner/3")]);
+    }
 }
\ No newline at end of file
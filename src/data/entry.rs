@@ -0,0 +1,76 @@
+use super::types::*;
+use super::constraints::ConstraintTable;
+
+use cat::*;
+
+/// A single rule of a domain, that can be left out to relax it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Entry {
+    /// The restriction on the values of a key.
+    Restriction(Num<Key>),
+    /// The constraint from an origin key on a target key.
+    Constraint(Num<Key>, Num<Key>),
+    /// An all-different group, by index.
+    AllDifferent(usize),
+    /// Bounds on how often a value is assigned, by index.
+    Cardinality(usize),
+}
+
+impl Domain {
+    /// All entries that actually restrict this domain.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        for (key_num, restriction) in self.key_restrictions.enumerate() {
+            if !restriction.is_trivial() {
+                entries.push(Entry::Restriction(key_num));
+            }
+        }
+        for (origin, target, _) in self.constraint_table.iter() {
+            entries.push(Entry::Constraint(origin, target));
+        }
+        for idx in 0..self.all_different.len() {
+            entries.push(Entry::AllDifferent(idx));
+        }
+        for idx in 0..self.cardinality.len() {
+            entries.push(Entry::Cardinality(idx));
+        }
+        entries
+    }
+
+    /// A copy of this domain that only has the given entries.
+    pub fn with_entries(&self, entries: &[Entry]) -> Domain {
+        let mut key_restrictions = self.keys.map_nums(|_| Restriction::Not(vec![]));
+        let mut constraint_table = ConstraintTable::new(self.keys.count());
+        let mut all_different = Vec::new();
+        let mut cardinality = Vec::new();
+
+        for &entry in entries.iter() {
+            match entry {
+                Entry::Restriction(key_num) => {
+                    key_restrictions[key_num] = self.key_restrictions[key_num].clone();
+                }
+                Entry::Constraint(origin, target) => {
+                    if let Some(restrictor) = self.constraint_table.get(origin, target) {
+                        constraint_table.set(origin, target, restrictor.clone());
+                    }
+                }
+                Entry::AllDifferent(idx) => {
+                    all_different.push(self.all_different[idx].clone());
+                }
+                Entry::Cardinality(idx) => {
+                    cardinality.push(self.cardinality[idx].clone());
+                }
+            }
+        }
+
+        Domain {
+            keys: self.keys.clone(),
+            values: self.values.clone(),
+            key_restrictions,
+            constraint_table,
+            all_different,
+            blank: self.blank,
+            cardinality,
+        }
+    }
+}
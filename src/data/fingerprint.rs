@@ -0,0 +1,162 @@
+use super::types::*;
+
+use cat::*;
+
+use std::hash::Hasher;
+
+/// 64-bit FNV-1a hasher. Unlike the std hashers its output is stable across
+/// builds, so fingerprints can be stored in files.
+pub struct Fingerprint {
+    state: u64,
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Fingerprint { state: FNV_OFFSET }
+    }
+
+    pub fn of_bytes(bytes: &[u8]) -> u64 {
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.write(bytes);
+        fingerprint.finish()
+    }
+}
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Fingerprint::new()
+    }
+}
+
+impl Hasher for Fingerprint {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= u64::from(byte);
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+impl Fingerprint {
+    // Everything is written as explicit little-endian bytes, rather than
+    // through `Hash`, whose output may change between Rust versions.
+
+    fn write_len(&mut self, len: usize) {
+        self.write(&(len as u64).to_le_bytes());
+    }
+
+    fn write_num<D>(&mut self, num: Num<D>) {
+        self.write_len(num.as_usize());
+    }
+
+    fn write_nums<D>(&mut self, nums: &[Num<D>]) {
+        self.write_len(nums.len());
+        for &num in nums.iter() {
+            self.write_num(num);
+        }
+    }
+
+    fn write_name(&mut self, name: &str) {
+        self.write_len(name.len());
+        self.write(name.as_bytes());
+    }
+
+    fn write_restriction(&mut self, restriction: &Restriction) {
+        match restriction {
+            Restriction::Not(values) => {
+                self.write(&[0]);
+                self.write_nums(values);
+            }
+            Restriction::Only(values) => {
+                self.write(&[1]);
+                self.write_nums(values);
+            }
+        }
+    }
+
+    fn write_option(&mut self, value: Option<usize>) {
+        match value {
+            Some(value) => {
+                self.write(&[1]);
+                self.write_len(value);
+            }
+            None => self.write(&[0]),
+        }
+    }
+}
+
+impl Domain {
+    /// Identifies the problem this domain describes: two domains with the
+    /// same fingerprint have the same solutions.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fingerprint::new();
+        self.hash_names(&mut hasher);
+        for (_, restriction) in self.key_restrictions.enumerate() {
+            hasher.write_restriction(restriction);
+        }
+        self.hash_constraints(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Like `fingerprint`, but leaving out the restrictions on the values of
+    /// single keys. Two domains with the same constraint fingerprint differ
+    /// at most in which values their keys allow.
+    pub fn constraint_fingerprint(&self) -> u64 {
+        let mut hasher = Fingerprint::new();
+        self.hash_names(&mut hasher);
+        self.hash_constraints(&mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_names(&self, hasher: &mut Fingerprint) {
+        hasher.write_len(self.keys.count().as_usize());
+        for (_, name) in self.keys.enumerate() {
+            hasher.write_name(name);
+        }
+        hasher.write_len(self.values.count().as_usize());
+        for (_, name) in self.values.enumerate() {
+            hasher.write_name(name);
+        }
+    }
+
+    fn hash_constraints(&self, hasher: &mut Fingerprint) {
+        hasher.write_len(self.constraint_table.len());
+        for (origin, target, restrictor) in self.constraint_table.iter() {
+            hasher.write_num(origin);
+            hasher.write_num(target);
+            for (_, restriction) in restrictor.enumerate() {
+                hasher.write_restriction(restriction);
+            }
+        }
+        hasher.write_len(self.all_different.len());
+        for group in self.all_different.iter() {
+            hasher.write_nums(group);
+        }
+        hasher.write_option(self.blank.map(|value_num| value_num.as_usize()));
+        hasher.write_len(self.cardinality.len());
+        for c in self.cardinality.iter() {
+            hasher.write_num(c.value);
+            hasher.write_len(c.min);
+            hasher.write_option(c.max);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fnv() {
+        // the reference values of 64-bit FNV-1a
+        assert_eq!(Fingerprint::of_bytes(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(Fingerprint::of_bytes(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,198 @@
+use super::types::*;
+
+use cat::*;
+
+use std::fmt;
+
+/// A suspicious part of a domain: probably a mistake, but not an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// Origin constrains target, but target does not constrain origin.
+    OneDirectional { origin: String, target: String },
+    /// No key can take this value.
+    UnusedValue { value: String },
+    /// The key restriction allows no value at all.
+    EmptyOnly { key: String },
+    /// More than one restriction was declared on the key; only the last one
+    /// is used. Holds the positions of the declarations in the file.
+    DuplicateRestriction { key: String, declarations: Vec<usize> },
+    /// More than one constraint was declared from origin on target; all of
+    /// them have to hold. Holds the positions of the declarations in the
+    /// file.
+    DuplicateConstraint {
+        origin: String,
+        target: String,
+        declarations: Vec<usize>,
+    },
+    /// A constraint mentions a value that key can never take.
+    ExcludedValue {
+        origin: String,
+        target: String,
+        key: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::OneDirectional { origin, target } => write!(
+                f, "{} constrains {}, but not the other way around",
+                origin, target
+            ),
+            Lint::UnusedValue { value } => write!(
+                f, "no key can take value {}", value
+            ),
+            Lint::EmptyOnly { key } => write!(
+                f, "the restriction of {} allows no value", key
+            ),
+            Lint::DuplicateRestriction { key, declarations } => write!(
+                f, "{} is restricted more than once, by {}; only the last \
+                    restriction is used",
+                key, positions("restrictions", declarations)
+            ),
+            Lint::DuplicateConstraint { origin, target, declarations } => write!(
+                f, "constraint from {} on {} is declared more than once, by \
+                    {}; the declarations are combined",
+                origin, target, positions("constraints", declarations)
+            ),
+            Lint::ExcludedValue { origin, target, key, value } => write!(
+                f, "constraint from {} on {} mentions value {}, which {} \
+                    can never take",
+                origin, target, value, key
+            ),
+        }
+    }
+}
+
+/// Refer to elements of a list in a domain file by their position.
+fn positions(section: &str, declarations: &[usize]) -> String {
+    let positions: Vec<String> = declarations.iter()
+        .map(|idx| format!("{}[{}]", section, idx))
+        .collect();
+    positions.join(", ")
+}
+
+impl Domain {
+    /// Look for suspicious restrictions and constraints.
+    pub fn lint(&self) -> Vec<Lint> {
+        let mut lints = Vec::new();
+
+        for (key_num, restriction) in self.key_restrictions.enumerate() {
+            if let Restriction::Only(values) = restriction {
+                if values.is_empty() {
+                    lints.push(Lint::EmptyOnly {
+                        key: self.keys[key_num].clone(),
+                    });
+                }
+            }
+        }
+
+        for (value_num, name) in self.values.enumerate() {
+            let used = self.key_restrictions.enumerate().any(|(_, restriction)| {
+                restriction.allows(value_num)
+            });
+            if !used {
+                lints.push(Lint::UnusedValue { value: name.clone() });
+            }
+        }
+
+        for (origin, target, restrictor) in self.constraint_table.iter() {
+            if !self.constrains(target, origin) {
+                lints.push(Lint::OneDirectional {
+                    origin: self.keys[origin].clone(),
+                    target: self.keys[target].clone(),
+                });
+            }
+            self.lint_excluded_values(origin, target, restrictor, &mut lints);
+        }
+
+        lints
+    }
+
+    /// Report the values a constraint mentions that its origin or target
+    /// can never take.
+    fn lint_excluded_values(&self,
+                            origin: Num<Key>,
+                            target: Num<Key>,
+                            restrictor: &Restrictor,
+                            lints: &mut Vec<Lint>)
+    {
+        let mut found = Vec::new();
+        for (value_num, restriction) in restrictor.enumerate() {
+            if restriction.is_trivial() {
+                continue;
+            }
+            if !self.key_restrictions[origin].allows(value_num) {
+                found.push((origin, value_num));
+            }
+
+            let values = match restriction {
+                Restriction::Not(values) => values,
+                Restriction::Only(values) => values,
+            };
+            for &target_value in values.iter() {
+                let pair = (target, target_value);
+                if !self.key_restrictions[target].allows(target_value) &&
+                    !found.contains(&pair)
+                {
+                    found.push(pair);
+                }
+            }
+        }
+
+        lints.extend(found.into_iter().map(|(key_num, value_num)| {
+            Lint::ExcludedValue {
+                origin: self.keys[origin].clone(),
+                target: self.keys[target].clone(),
+                key: self.keys[key_num].clone(),
+                value: self.values[value_num].clone(),
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use failure::ResultExt;
+    use json;
+
+    fn names(origin: &str, target: &str) -> (String, String) {
+        (origin.to_string(), target.to_string())
+    }
+
+    #[test]
+    fn test_lint() {
+        let mut domain = json::read_config("abcABC.json")
+            .context("Could not parse domain").unwrap();
+
+        // the constraints of abcABC are all declared in one direction
+        let lints = domain.lint();
+        assert_eq!(lints.len(), 6);
+        let (origin, target) = names("1/lower", "1/upper");
+        assert!(lints.contains(&Lint::OneDirectional { origin, target }));
+
+        let upper = domain.keys.enumerate()
+            .find(|&(_, n)| n == "1/upper").unwrap().0;
+        domain.key_restrictions[upper] = Restriction::Only(vec![]);
+        let lints = domain.lint();
+        assert!(lints.contains(&Lint::EmptyOnly { key: "1/upper".to_string() }));
+        let (origin, target) = names("1/lower", "1/upper");
+        assert!(lints.contains(&Lint::ExcludedValue {
+            origin,
+            target,
+            key: "1/upper".to_string(),
+            value: "A".to_string(),
+        }));
+        // the other uppercase keys can still take A
+        assert!(!lints.contains(&Lint::UnusedValue { value: "A".to_string() }));
+
+        let a = domain.values.enumerate().find(|&(_, n)| n == "a").unwrap().0;
+        for key_num in domain.keys.nums() {
+            domain.key_restrictions[key_num] = Restriction::Not(vec![a]);
+        }
+        let lints = domain.lint();
+        assert!(lints.contains(&Lint::UnusedValue { value: "a".to_string() }));
+    }
+}
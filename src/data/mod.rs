@@ -1,7 +1,22 @@
 mod types;
+mod constraints;
 mod restricted_range;
+mod bitset_range;
+mod value_range;
 mod domain_walker;
+mod nogoods;
+mod fingerprint;
+mod all_different;
+mod entry;
+mod lint;
 
 pub use self::types::*;
+pub use self::constraints::ConstraintTable;
 pub use self::restricted_range::RestrictedRange;
-pub use self::domain_walker::DomainWalker;
\ No newline at end of file
+pub use self::bitset_range::BitsetRange;
+pub use self::value_range::{RangeKind, ValueRange};
+pub use self::domain_walker::{DomainWalker, Checkpoint};
+pub use self::nogoods::Nogood;
+pub use self::fingerprint::Fingerprint;
+pub use self::entry::Entry;
+pub use self::lint::Lint;
@@ -0,0 +1,106 @@
+use super::types::*;
+
+use cat::*;
+
+use std::collections::HashSet;
+
+/// A set of assignments that cannot all be part of the same solution.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Nogood {
+    assignments: Vec<Assignment>,
+}
+
+impl Nogood {
+    pub fn new(mut assignments: Vec<Assignment>) -> Self {
+        assignments.sort_by_key(|a| (a.key_num, a.value_num));
+        assignments.dedup();
+        Nogood { assignments }
+    }
+
+    pub fn assignments(&self) -> &[Assignment] {
+        &self.assignments
+    }
+
+    pub fn len(&self) -> usize {
+        self.assignments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assignments.is_empty()
+    }
+}
+
+/// Collection of nogoods, indexed by the keys they involve.
+pub struct NogoodStore {
+    nogoods: Vec<Nogood>,
+    known: HashSet<Nogood>,
+    by_key: Table<Key, Vec<usize>>,
+}
+
+impl NogoodStore {
+    pub fn new(key_count: Count<Key>) -> Self {
+        NogoodStore {
+            nogoods: Vec::new(),
+            known: HashSet::new(),
+            by_key: key_count.map_nums(|_| Vec::new()),
+        }
+    }
+
+    /// Add a nogood to the store. Returns false if it was already known.
+    pub fn insert(&mut self, nogood: Nogood) -> bool {
+        if self.known.contains(&nogood) {
+            return false;
+        }
+
+        let idx = self.nogoods.len();
+        for a in nogood.assignments() {
+            self.by_key[a.key_num].push(idx);
+        }
+        self.known.insert(nogood.clone());
+        self.nogoods.push(nogood);
+        true
+    }
+
+    pub fn nogoods(&self) -> &[Nogood] {
+        &self.nogoods
+    }
+
+    /// Indices of the nogoods that involve given key.
+    pub fn involving(&self, key_num: Num<Key>) -> &[usize] {
+        &self.by_key[key_num]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nogoods.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nogoods.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cat::internal::{to_count, to_num};
+
+    fn assignment(key: usize, value: usize) -> Assignment {
+        Assignment { key_num: to_num(key), value_num: to_num(value) }
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut store = NogoodStore::new(to_count(3));
+        let nogood = Nogood::new(vec![assignment(2, 0), assignment(0, 1)]);
+        assert!(store.insert(nogood));
+
+        // the order of assignments does not matter
+        let same = Nogood::new(vec![assignment(0, 1), assignment(2, 0)]);
+        assert!(!store.insert(same));
+
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.involving(to_num(0)), &[0]);
+        assert!(store.involving(to_num(1)).is_empty());
+        assert_eq!(store.involving(to_num(2)), &[0]);
+    }
+}
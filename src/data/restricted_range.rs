@@ -50,7 +50,7 @@ impl<T> Permutation<T> {
 impl<T> Index<usize> for Permutation<T> {
     type Output = Num<T>;
 
-    fn index<'a>(&'a self, idx: usize) -> &'a Num<T> {
+    fn index(&self, idx: usize) -> &Num<T> {
         &self.items[idx]
     }
 }
@@ -58,7 +58,7 @@ impl<T> Index<usize> for Permutation<T> {
 impl<T> Index<Range<usize>> for Permutation<T> {
     type Output = [Num<T>];
 
-    fn index<'a>(&'a self, range: Range<usize>) -> &'a [Num<T>] {
+    fn index(&self, range: Range<usize>) -> &[Num<T>] {
         &self.items[range]
     }
 }
@@ -78,7 +78,7 @@ pub struct Segment {
 impl Segment {
     pub fn empty(offset: usize) -> Self {
         Segment {
-            offset: offset,
+            offset,
             num_rejected: 0,
         }
     }
@@ -134,7 +134,7 @@ impl<T> SegmentedPermutation<T> {
         self.item_segment[item_num] = segment - 1;
     }
 
-    pub fn segments<'a>(&'a self) -> &'a [Segment] {
+    pub fn segments(&self) -> &[Segment] {
         &self.segments
     }
 
@@ -222,21 +222,21 @@ impl<T> SegmentedPermutation<T> {
         self.segments[segment].num_rejected -= 1;
         let frontier = self.segments[segment].frontier();
         self.items.swap(pos, frontier);
-        return frontier;
+        frontier
     }
 
     fn reject_pos(&mut self, segment: usize, pos: usize) -> usize {
         let frontier = self.segments[segment].frontier();
         self.items.swap(pos, frontier);
         self.segments[segment].num_rejected += 1;
-        return frontier;
+        frontier
     }
 }
 
 impl<T> Index<usize> for SegmentedPermutation<T> {
     type Output = Num<T>;
 
-    fn index<'a>(&'a self, idx: usize) -> &'a Num<T> {
+    fn index(&self, idx: usize) -> &Num<T> {
         &self.items[idx]
     }
 }
@@ -244,7 +244,7 @@ impl<T> Index<usize> for SegmentedPermutation<T> {
 impl<T> Index<Range<usize>> for SegmentedPermutation<T> {
     type Output = [Num<T>];
 
-    fn index<'a>(&'a self, range: Range<usize>) -> &'a [Num<T>] {
+    fn index(&self, range: Range<usize>) -> &[Num<T>] {
         &self.items[range]
     }
 }
@@ -263,18 +263,18 @@ impl<T> RestrictedRange<T> {
         }
     }
 
-    pub fn accepted<'a>(&'a self) -> &'a [Num<T>] {
-        &self.values[(self.frontier()..self.values.len())]
+    pub fn accepted(&self) -> &[Num<T>] {
+        &self.values[self.frontier()..self.values.len()]
     }
 
-    pub fn rejected<'a>(&'a self) -> &'a [Num<T>] {
-        &self.values[(0..self.frontier())]
+    pub fn rejected(&self) -> &[Num<T>] {
+        &self.values[0..self.frontier()]
     }
 
     pub fn accepts(&self, value_num: Num<T>) -> bool {
         let segment = self.values.segments().last().unwrap();
         let pos = self.values.pos(value_num);
-        return segment.accepts(pos);
+        segment.accepts(pos)
     }
 
     // returns the values that were rejected by this operation
@@ -288,7 +288,7 @@ impl<T> RestrictedRange<T> {
         }
 
         // When a value is rejected, it is placed behind the frontier.
-        return &self.values[(prev_frontier..self.frontier())];
+        &self.values[prev_frontier..self.frontier()]
     }
 
     pub fn remove_rejection<'a>(&'a mut self, rejected: &[Num<T>])
@@ -300,7 +300,7 @@ impl<T> RestrictedRange<T> {
         }
         
         // when a value is unrejected, it is placed in front of the frontier.
-        return &self.values[(self.frontier()..prev_frontier)];
+        &self.values[self.frontier()..prev_frontier]
     }
 
     // returns the values that were rejected by this operation
@@ -320,7 +320,7 @@ impl<T> RestrictedRange<T> {
         }
 
         let last_segment = &self.values.segments[num_segments -1];
-        return &self.values[(frontier..last_segment.offset)];
+        &self.values[frontier..last_segment.offset]
     }
 
     // returns the values that are now allowed because of this operation
@@ -345,7 +345,7 @@ impl<T> RestrictedRange<T> {
         // moved.
         let prev_frontier = self.frontier();
         self.values.pop_segment();
-        return &self.values[self.frontier()..prev_frontier];
+        &self.values[self.frontier()..prev_frontier]
     }
 
     pub fn reject(&mut self, value_num: Num<T>) {
@@ -364,7 +364,7 @@ impl<T> RestrictedRange<T> {
 
     fn frontier(&self) -> usize {
         let last_segment = self.values.segments().last().unwrap();
-        return last_segment.frontier();
+        last_segment.frontier()
     }
 }
 
@@ -398,11 +398,11 @@ mod test {
             segment_end = segments[segment_num].offset;
         }
 
-        return SegmentedPermutation {
+        SegmentedPermutation {
             items,
             segments,
             item_segment,
-        };
+        }
     }
 
     // TODO: oh please break this function up
@@ -456,7 +456,7 @@ mod test {
             }
         });
 
-        return RestrictedRange { values, times_rejected };
+        RestrictedRange { values, times_rejected }
     }
 
     fn remove_segment<T: Clone>(p: &SegmentedPermutation<T>, segment_num: usize)
@@ -480,7 +480,7 @@ mod test {
             prev_segment.num_rejected += segment.num_rejected;
         }
 
-        return segment_permutation(items, segments);
+        segment_permutation(items, segments)
     }
 
 
@@ -510,12 +510,12 @@ mod test {
         fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
             let count = to_count(runner.rng().gen_range(1, self.max_size));
             let range = generate_range(runner.rng(), count);
-            return Ok(
+            Ok(
                 DomainShrinkerTree::new(
                     count,
                     SimpleValueTree::new(RestrictedRangeSubtree::new(range)),
                 )
-            );
+            )
         }
     }
 
@@ -563,7 +563,7 @@ mod test {
             let child = RestrictedRange { values, times_rejected };
 
             self.next_shrink = RemoveSegment(to_remove + 1);
-            return Some(RestrictedRangeSubtree::new(child));
+            Some(RestrictedRangeSubtree::new(child))
         }
 
     }
@@ -740,7 +740,7 @@ mod test {
                 // swap role of last element and removed element
                 table[to_remove] = self[to_num(new_count.as_usize())].clone();
             }
-            return table;
+            table
         }
     }
 
@@ -749,7 +749,7 @@ mod test {
         for (pos, &num) in items.iter().enumerate() {
             positions[num] = pos;
         }
-        return Permutation { items, positions };
+        Permutation { items, positions }
     }
 
     impl<T> ShrinkDomain<T> for Permutation<T> {
@@ -760,7 +760,7 @@ mod test {
             items[self.positions[last]] = to_remove;
             items.remove(self.positions[to_remove]);
 
-            return permutation_from_vec(items);
+            permutation_from_vec(items)
         }
     }
 
@@ -772,14 +772,14 @@ mod test {
                 segments[segment_num].num_rejected -= 1;
             }
             // shift all following segments one place to the left
-            for i in (segment_num+1)..segments.len() {
-                segments[i].offset -= 1;
+            for segment in segments.iter_mut().skip(segment_num + 1) {
+                segment.offset -= 1;
             }
-            return SegmentedPermutation {
+            SegmentedPermutation {
                 items: self.items.shrink_remove(to_remove),
                 item_segment: self.item_segment.shrink_remove(to_remove),
                 segments,
-            };
+            }
         }
     }
 
@@ -824,7 +824,7 @@ mod test {
             self.next_step.next().map(|to_remove| {
                 let count = to_count(self.count.as_usize() - 1);
                 let value = self.value.shrink_remove(to_remove);
-                return Self::new(count, value);
+                Self::new(count, value)
             })
         }
     }
@@ -881,7 +881,7 @@ mod test {
 
     fn check_range_integrity<T>(range: &RestrictedRange<T>) {
         check_segments(&range.values);
-        check_rejects(&range);
+        check_rejects(range);
     }
 
     #[derive(Clone)]
@@ -895,7 +895,7 @@ mod test {
             for &num in items {
                 included[num] = true;
             }
-            return Subset { included };
+            Subset { included }
         }
 
         fn iter<'a>(&'a self) -> impl Iterator<Item = Num<T>> + 'a {
@@ -931,7 +931,7 @@ mod test {
 
         items.shuffle(rng);
         let num_items = rng.gen_range(0, items.len());
-        return Subset::from_items(count, &items[0..num_items]);
+        Subset::from_items(count, &items[0..num_items])
     }
 
 
@@ -1140,7 +1140,7 @@ mod test {
     {
         let mut vec = values.to_vec();
         vec.sort();
-        return vec;
+        vec
     }
 
     fn diff<T>(fst: &RestrictedRange<T>, snd: &RestrictedRange<T>)
@@ -1148,7 +1148,7 @@ mod test {
     {
         let mut vec = sorted(fst.accepted());
         vec.retain(|&num| !snd.accepts(num));
-        return vec;
+        vec
     }
 
     proptest! {
@@ -1,6 +1,8 @@
 use cat;
 use cat::*;
 
+use super::constraints::ConstraintTable;
+
 /// Indicator struct for a key
 #[derive(Debug)]
 pub struct Key;
@@ -10,7 +12,7 @@ pub struct Key;
 pub struct Value;
 
 /// Simple key/value pair
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Assignment {
     pub key_num: Num<Key>,
     pub value_num: Num<Value>,
@@ -34,7 +36,7 @@ impl AssignmentNum {
 impl HasCount<Assignment> for AssignmentNum {
     fn count(&self) -> Count<Assignment> {
         let count = self.product_num().count();
-        return cat::internal::to_count(count.as_usize());
+        cat::internal::to_count(count.as_usize())
     }
 }
 
@@ -44,7 +46,7 @@ impl Mapping<Assignment> for AssignmentNum {
     fn apply(&self, assignment: Assignment) -> Num<Assignment> {
         let pair = (assignment.key_num, assignment.value_num);
         let num = self.product_num().apply(pair);
-        return cat::internal::to_num(num.as_usize());
+        cat::internal::to_num(num.as_usize())
     }
 }
 
@@ -55,12 +57,14 @@ pub struct AllowedAssignment;
 
 type AssignmentTable<T> = Composed<AssignmentNum, Table<Assignment, T>>;
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Restriction {
     Not(Vec<Num<Value>>),
     Only(Vec<Num<Value>>),
 }
 
 impl Restriction {
+    /// The restriction that allows exactly the values this one rules out.
     pub fn inverse(&self) -> Restriction {
         match self {
             Restriction::Not(values) => {
@@ -72,6 +76,50 @@ impl Restriction {
         }
     }
 
+    /// The restriction that allows the values both restrictions allow.
+    pub fn intersect(&self, other: &Restriction) -> Restriction {
+        match (self, other) {
+            (Restriction::Not(a), Restriction::Not(b)) => {
+                let mut values = a.clone();
+                values.extend(b.iter().filter(|v| !a.contains(v)));
+                Restriction::Not(values)
+            }
+            (Restriction::Only(a), Restriction::Only(b)) => {
+                Restriction::Only(a.iter().cloned().filter(|v| b.contains(v)).collect())
+            }
+            (Restriction::Only(a), Restriction::Not(b)) |
+            (Restriction::Not(b), Restriction::Only(a)) => {
+                Restriction::Only(a.iter().cloned().filter(|v| !b.contains(v)).collect())
+            }
+        }
+    }
+
+    /// The restriction that allows the values either restriction allows.
+    pub fn union(&self, other: &Restriction) -> Restriction {
+        match (self, other) {
+            (Restriction::Not(a), Restriction::Not(b)) => {
+                Restriction::Not(a.iter().cloned().filter(|v| b.contains(v)).collect())
+            }
+            (Restriction::Only(a), Restriction::Only(b)) => {
+                let mut values = a.clone();
+                values.extend(b.iter().filter(|v| !a.contains(v)));
+                Restriction::Only(values)
+            }
+            (Restriction::Only(a), Restriction::Not(b)) |
+            (Restriction::Not(b), Restriction::Only(a)) => {
+                Restriction::Not(b.iter().cloned().filter(|v| !a.contains(v)).collect())
+            }
+        }
+    }
+
+    /// Whether this restriction trivially allows every value.
+    pub fn is_trivial(&self) -> bool {
+        match self {
+            Restriction::Not(values) => values.is_empty(),
+            Restriction::Only(_) => false,
+        }
+    }
+
     pub fn allows(&self, value_num: Num<Value>) -> bool {
         match self {
             Restriction::Not(values) => {
@@ -86,6 +134,15 @@ impl Restriction {
 
 pub type Restrictor = Table<Value, Restriction>;
 
+/// Bounds on the number of keys a value is assigned to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cardinality {
+    pub value: Num<Value>,
+    pub min: usize,
+    /// None when there is no upper bound
+    pub max: Option<usize>,
+}
+
 pub struct KeyRestriction {
     pub key: Num<Key>,
     pub restriction: Restriction,
@@ -97,6 +154,24 @@ pub struct Constraint {
     pub restrictor: Restrictor,
 }
 
+impl Constraint {
+    /// The same constraint, as seen from the target: a target value rules
+    /// out the origin values that rule it out.
+    pub fn inverse(&self) -> Constraint {
+        let restrictor = self.restrictor.map_nums(|target_value| {
+            Restriction::Not(self.restrictor.enumerate()
+                .filter(|&(_, restriction)| !restriction.allows(target_value))
+                .map(|(origin_value, _)| origin_value)
+                .collect())
+        });
+        Constraint {
+            origin: self.target,
+            target: self.origin,
+            restrictor,
+        }
+    }
+}
+
 
 pub struct Domain {
     /// key names
@@ -105,9 +180,16 @@ pub struct Domain {
     pub values: Table<Value, String>,
     /// individual key restrictions
     pub key_restrictions: Table<Key, Restriction>,
-    /// assignment constraints
-    // TODO: get rid of nested table
-    pub constraint_table: Table<Key, Table<Key, Restrictor>>,
+    /// assignment constraints, for the constrained pairs of keys only
+    pub constraint_table: ConstraintTable,
+    /// groups of keys that all have to be assigned a different value
+    pub all_different: Vec<Vec<Num<Key>>>,
+    /// value that leaves a key empty; it can be assigned any number of
+    /// times, all-different groups included
+    pub blank: Option<Num<Value>>,
+    /// bounds on how often values are assigned; the maximum of a value also
+    /// applies within all-different groups, where it would be one otherwise
+    pub cardinality: Vec<Cardinality>,
 }
 
 impl Domain {
@@ -117,4 +199,27 @@ impl Domain {
             value_count: self.values.count(),
         }
     }
+
+    /// How many keys of an all-different group can take the given value;
+    /// None when there is no limit.
+    pub fn group_capacity(&self, value_num: Num<Value>) -> Option<usize> {
+        if Some(value_num) == self.blank {
+            return None;
+        }
+        let max = self.cardinality.iter()
+            .filter(|c| c.value == value_num)
+            .filter_map(|c| c.max)
+            .min();
+        Some(max.unwrap_or(1))
+    }
+
+    /// Whether assigning origin can restrict the values of target.
+    pub fn constrains(&self, origin: Num<Key>, target: Num<Key>) -> bool {
+        self.constraint_table.get(origin, target).is_some()
+    }
+
+    /// Whether the two given assignments can be made together.
+    pub fn compatible(&self, a: Assignment, b: Assignment) -> bool {
+        self.constraint_table.allows(a, b) && self.constraint_table.allows(b, a)
+    }
 }
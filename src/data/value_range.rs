@@ -0,0 +1,114 @@
+use super::{BitsetRange, RestrictedRange};
+
+use cat::*;
+
+/// From this many values on, ranges are kept as bitsets.
+const BITSET_MIN_VALUES: usize = 64;
+
+/// How the values remaining for a key are represented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeKind {
+    /// A `RestrictedRange`; cheap to update when there are few values.
+    Permutation,
+    /// A `BitsetRange`; for domains with up to a few hundred values.
+    Bitset,
+}
+
+impl RangeKind {
+    /// The representation that suits a number of values best.
+    pub fn for_count<T>(count: Count<T>) -> Self {
+        if count.as_usize() >= BITSET_MIN_VALUES {
+            RangeKind::Bitset
+        } else {
+            RangeKind::Permutation
+        }
+    }
+}
+
+/// A range of values in the representation chosen at construction.
+#[derive(Debug, Clone)]
+pub enum ValueRange<T> {
+    Permutation(RestrictedRange<T>),
+    Bitset(BitsetRange<T>),
+}
+
+impl<T> ValueRange<T> {
+    pub fn new(kind: RangeKind, value_count: Count<T>) -> Self {
+        match kind {
+            RangeKind::Permutation => {
+                ValueRange::Permutation(RestrictedRange::new(value_count))
+            }
+            RangeKind::Bitset => ValueRange::Bitset(BitsetRange::new(value_count)),
+        }
+    }
+
+    pub fn accepted(&self) -> &[Num<T>] {
+        match self {
+            ValueRange::Permutation(range) => range.accepted(),
+            ValueRange::Bitset(range) => range.accepted(),
+        }
+    }
+
+    pub fn rejected(&self) -> &[Num<T>] {
+        match self {
+            ValueRange::Permutation(range) => range.rejected(),
+            ValueRange::Bitset(range) => range.rejected(),
+        }
+    }
+
+    pub fn accepts(&self, value_num: Num<T>) -> bool {
+        match self {
+            ValueRange::Permutation(range) => range.accepts(value_num),
+            ValueRange::Bitset(range) => range.accepts(value_num),
+        }
+    }
+
+    /// The number of values both ranges accept. Unless both are bitsets,
+    /// this goes through the values other accepts, so other should be the
+    /// smaller range.
+    pub fn intersection_len(&self, other: &ValueRange<T>) -> usize {
+        match (self, other) {
+            (ValueRange::Bitset(a), ValueRange::Bitset(b)) => a.intersection_len(b),
+            _ => {
+                other.accepted().iter().filter(|&&value_num| self.accepts(value_num)).count()
+            }
+        }
+    }
+
+    /// Whether any value is accepted by both ranges. Like
+    /// `intersection_len`, other should be the smaller range.
+    pub fn intersects(&self, other: &ValueRange<T>) -> bool {
+        match (self, other) {
+            (ValueRange::Bitset(a), ValueRange::Bitset(b)) => a.intersects(b),
+            _ => other.accepted().iter().any(|&value_num| self.accepts(value_num)),
+        }
+    }
+
+    pub fn add_rejection<'a>(&'a mut self, rejected: &[Num<T>]) -> &'a [Num<T>] {
+        match self {
+            ValueRange::Permutation(range) => range.add_rejection(rejected),
+            ValueRange::Bitset(range) => range.add_rejection(rejected),
+        }
+    }
+
+    pub fn remove_rejection<'a>(&'a mut self, rejected: &[Num<T>]) -> &'a [Num<T>] {
+        match self {
+            ValueRange::Permutation(range) => range.remove_rejection(rejected),
+            ValueRange::Bitset(range) => range.remove_rejection(rejected),
+        }
+    }
+
+    pub fn add_restriction<'a>(&'a mut self, allowed: &[Num<T>]) -> &'a [Num<T>] {
+        match self {
+            ValueRange::Permutation(range) => range.add_restriction(allowed),
+            ValueRange::Bitset(range) => range.add_restriction(allowed),
+        }
+    }
+
+    pub fn remove_restriction<'a>(&'a mut self, allowed: &[Num<T>]) -> &'a [Num<T>] {
+        match self {
+            ValueRange::Permutation(range) => range.remove_restriction(allowed),
+            ValueRange::Bitset(range) => range.remove_restriction(allowed),
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use data::*;
 use cat::*;
 use cat::ops::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use Result;
 use super::reader::*;
+use super::sources::{Source, Sources};
+use super::templates::{Expansion, TemplateData};
 
 #[derive(Deserialize)]
 pub struct DomainData<'s> {
@@ -14,6 +16,40 @@ pub struct DomainData<'s> {
     restrictions: Vec<KeyRestrictionData<'s>>,
     #[serde(borrow)]
     constraints: Vec<ConstraintData<'s>>,
+    #[serde(borrow, default)]
+    all_different: Vec<AllDifferentData<'s>>,
+    #[serde(borrow, default)]
+    templates: Vec<TemplateData<'s>>,
+    /// value that leaves a key empty
+    #[serde(borrow, default)]
+    blank: Option<&'s str>,
+    /// values that may be left out; when given, every other value except
+    /// the blank has to be assigned to some key
+    #[serde(borrow, default)]
+    optional: Option<Vec<&'s str>>,
+    #[serde(borrow, default)]
+    cardinality: Vec<CardinalityData<'s>>,
+    /// named sets of keys, that can be referred to as `@name`
+    #[serde(borrow, default)]
+    key_sets: BTreeMap<&'s str, Vec<&'s str>>,
+    /// named sets of values, that can be referred to as `@name`
+    #[serde(borrow, default)]
+    value_sets: BTreeMap<&'s str, Vec<&'s str>>,
+}
+
+/// Keys that all have to be assigned a different value.
+#[derive(Deserialize)]
+pub struct AllDifferentData<'s>(#[serde(borrow)] Vec<&'s str>);
+
+/// Bounds on the number of keys each of the values is assigned to.
+#[derive(Deserialize)]
+pub struct CardinalityData<'s> {
+    #[serde(borrow)]
+    values: Vec<&'s str>,
+    #[serde(default)]
+    min: usize,
+    #[serde(default)]
+    max: Option<usize>,
 }
 
 #[derive(Deserialize)]
@@ -28,23 +64,97 @@ pub struct ConstraintData<'s> {
     origin: &'s str,
     target: &'s str,
     #[serde(borrow)]
-    restrictor: HashMap<&'s str, RestrictionData<'s>>,
+    restrictor: BTreeMap<&'s str, RestrictionData<'s>>,
+    /// whether the constraint also holds from target to origin
+    #[serde(default)]
+    symmetric: bool,
 }
 
 #[derive(Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[serde(rename_all = "snake_case")]
 pub enum RestrictionData<'s> {
     #[serde(borrow)]
     Not(Vec<&'s str>),
     #[serde(borrow)]
     Only(Vec<&'s str>),
+    /// the values from the first to the last, inclusive, in the order they
+    /// are declared in
+    Range(&'s str, &'s str),
+    /// the values all of these restrictions allow
+    #[serde(borrow)]
+    AllOf(Vec<RestrictionData<'s>>),
+    /// the values any of these restrictions allows
+    #[serde(borrow)]
+    AnyOf(Vec<RestrictionData<'s>>),
+    /// the values this restriction rules out
+    #[serde(borrow)]
+    Except(Box<RestrictionData<'s>>),
 }
 
 impl<'s> DomainData<'s> {
-    pub fn mk_name_reader(&self) -> NameReader<'s> {
+    pub fn mk_name_reader(&self) -> Result<NameReader<'s>> {
         let keys = Table::from_vec(self.keys.clone());
         let values = Table::from_vec(self.values.clone());
-        NameReader::new(keys, values)
+        let mut reader = NameReader::new(keys, values);
+        for (&name, members) in self.key_sets.iter() {
+            reader.add_key_set(name, members.clone())?;
+        }
+        for (&name, members) in self.value_sets.iter() {
+            reader.add_value_set(name, members.clone())?;
+        }
+        Ok(reader)
+    }
+
+    /// Look for keys with more than one restriction and pairs of keys with
+    /// more than one constraint declared on them. These are found in the
+    /// declarations rather than in the domain they make up, where they have
+    /// been merged.
+    pub fn lint(&self, reader: &NameReader<'s>) -> Result<Vec<Lint>> {
+        let mut lints = Vec::new();
+
+        let mut restricted: BTreeMap<Num<Key>, Vec<usize>> = BTreeMap::new();
+        for (idx, data) in self.restrictions.iter().enumerate() {
+            for key_num in reader.read_keys(data.key)? {
+                add_declaration(restricted.entry(key_num).or_default(), idx);
+            }
+        }
+        for (key_num, declarations) in restricted.into_iter() {
+            if declarations.len() > 1 {
+                lints.push(Lint::DuplicateRestriction {
+                    key: reader.keys()[key_num].to_string(),
+                    declarations,
+                });
+            }
+        }
+
+        let mut constrained: BTreeMap<(Num<Key>, Num<Key>), Vec<usize>> = BTreeMap::new();
+        for (idx, data) in self.constraints.iter().enumerate() {
+            let targets = reader.read_keys(data.target)?;
+            for origin in reader.read_keys(data.origin)? {
+                for &target in targets.iter().filter(|&&t| t != origin) {
+                    let declarations = constrained.entry((origin, target)).or_default();
+                    add_declaration(declarations, idx);
+                }
+            }
+        }
+        for ((origin, target), declarations) in constrained.into_iter() {
+            if declarations.len() > 1 {
+                lints.push(Lint::DuplicateConstraint {
+                    origin: reader.keys()[origin].to_string(),
+                    target: reader.keys()[target].to_string(),
+                    declarations,
+                });
+            }
+        }
+
+        Ok(lints)
+    }
+}
+
+// a pattern can match a key more than once
+fn add_declaration(declarations: &mut Vec<usize>, idx: usize) {
+    if declarations.last() != Some(&idx) {
+        declarations.push(idx);
     }
 }
 
@@ -52,108 +162,354 @@ impl<'s> Reader<Domain> for NameReader<'s> {
     type Repr = DomainData<'s>;
 
     fn read(&self, repr: DomainData<'s>) -> Result<Domain> {
-        let key_restrictions = self.read(repr.restrictions)?;
-        let constraint_table = self.read(repr.constraints)?;
-        Ok(Domain {
+        let (domain, _): (Domain, Sources) = self.read(repr)?;
+        Ok(domain)
+    }
+}
+
+impl<'s> Reader<(Domain, Sources)> for NameReader<'s> {
+    type Repr = DomainData<'s>;
+
+    /// Read a domain, keeping track of which parts of the file each of its
+    /// entries comes from.
+    fn read(&self, repr: DomainData<'s>) -> Result<(Domain, Sources)> {
+        let mut sources = Sources::new();
+
+        let mut restrictions = Vec::new();
+        for (idx, data) in repr.restrictions.into_iter().enumerate() {
+            let expanded: Vec<KeyRestriction> = self.read(data)?;
+            for r in expanded.into_iter() {
+                sources.replace(Entry::Restriction(r.key), Source::Restriction(idx));
+                restrictions.push(r);
+            }
+        }
+        let mut generated = Vec::new();
+        let mut all_different: Vec<Vec<Num<Key>>> = self.read_vec(repr.all_different)?;
+        for idx in 0..all_different.len() {
+            sources.add(Entry::AllDifferent(idx), Source::AllDifferent(idx));
+        }
+
+        let mut constraints = Vec::with_capacity(repr.constraints.len());
+        let mut symmetric_pairs = Vec::new();
+        for (idx, data) in repr.constraints.into_iter().enumerate() {
+            let symmetric = data.symmetric;
+            let expanded: Vec<Constraint> = self.read(data)?;
+            for c in expanded.into_iter() {
+                let source = Source::Constraint(idx);
+                if symmetric {
+                    symmetric_pairs.push((c.origin, c.target));
+                    sources.add(Entry::Constraint(c.target, c.origin), source);
+                    constraints.push(c.inverse());
+                }
+                sources.add(Entry::Constraint(c.origin, c.target), source);
+                constraints.push(c);
+            }
+        }
+
+        for (idx, template) in repr.templates.into_iter().enumerate() {
+            let expansion: Expansion = self.read(template)?;
+            let source = Source::Template(idx);
+            for r in expansion.restrictions.iter() {
+                sources.add(Entry::Restriction(r.key), source);
+            }
+            for c in expansion.constraints.iter() {
+                sources.add(Entry::Constraint(c.origin, c.target), source);
+            }
+            for group_idx in 0..expansion.all_different.len() {
+                let entry = Entry::AllDifferent(all_different.len() + group_idx);
+                sources.add(entry, source);
+            }
+            generated.extend(expansion.restrictions);
+            constraints.extend(expansion.constraints);
+            all_different.extend(expansion.all_different);
+        }
+
+        let blank = match repr.blank {
+            Some(value_name) => Some(self.read(value_name)?),
+            None => None,
+        };
+        let mut cardinality = Vec::new();
+        for (idx, data) in repr.cardinality.into_iter().enumerate() {
+            let expanded: Vec<Cardinality> = self.read(data)?;
+            for c in expanded.into_iter() {
+                sources.add(Entry::Cardinality(cardinality.len()), Source::Cardinality(idx));
+                cardinality.push(c);
+            }
+        }
+        if let Some(optional) = repr.optional {
+            let optional = self.read_value_list(&optional)?;
+            for value_num in self.values().nums() {
+                if Some(value_num) != blank && !optional.contains(&value_num) {
+                    sources.add(Entry::Cardinality(cardinality.len()), Source::Optional);
+                    cardinality.push(Cardinality { value: value_num, min: 1, max: None });
+                }
+            }
+        }
+
+        let domain = Domain {
             keys: self.keys().map(|key_name| key_name.to_string()),
             values: self.values().map(|value_name| value_name.to_string()),
-            key_restrictions,
-            constraint_table,
-        })
+            key_restrictions: self.restriction_table(restrictions, generated)?,
+            constraint_table: self.constraint_table(constraints, &symmetric_pairs)?,
+            all_different,
+            blank,
+            cardinality,
+        };
+        Ok((domain, sources))
     }
 }
 
-impl<'s> Reader<Table<Key, Restriction>> for NameReader<'s> {
-    type Repr = Vec<KeyRestrictionData<'s>>;
-
-    fn read(&self, repr: Self::Repr) -> Result<Table<Key, Restriction>> {
+impl<'s> NameReader<'s> {
+    /// Build the restriction per key. Of the restrictions in the domain, the
+    /// last one on a key is used; the ones generated by templates all have to
+    /// hold on top of that.
+    fn restriction_table(&self,
+                         restrictions: Vec<KeyRestriction>,
+                         generated: Vec<KeyRestriction>)
+                         -> Result<Table<Key, Restriction>>
+    {
         let mut table = self.keys().map_nums(|_| Restriction::Not(vec![]));
-        let restrictions: Vec<KeyRestriction> = self.read_vec(repr)?;
         for r in restrictions.into_iter() {
             table[r.key] = r.restriction;
         }
-        return Ok(table);
+        for r in generated.into_iter() {
+            let combined = table[r.key].intersect(&r.restriction);
+            if self.allows_none(&combined) &&
+                !self.allows_none(&table[r.key]) &&
+                !self.allows_none(&r.restriction)
+            {
+                bail!("restrictions on {} contradict each other",
+                      self.keys()[r.key]);
+            }
+            table[r.key] = combined;
+        }
+        Ok(table)
     }
-}
 
-impl<'s> Reader<Table<Key, Table<Key, Restrictor>>> for NameReader<'s> {
-    type Repr = Vec<ConstraintData<'s>>;
+    /// Build the constraint table; constraints on the same pair of keys all
+    /// have to hold. The pairs that were declared symmetric are checked to
+    /// be consistent in both directions.
+    fn constraint_table(&self,
+                        constraints: Vec<Constraint>,
+                        symmetric_pairs: &[(Num<Key>, Num<Key>)])
+                        -> Result<ConstraintTable>
+    {
+        // the declared constraints per pair of keys, including those that
+        // turn out not to restrict anything
+        let mut declared: HashMap<(Num<Key>, Num<Key>), Restrictor> = HashMap::new();
+
+        for c in constraints.into_iter() {
+            let restrictor = match declared.remove(&(c.origin, c.target)) {
+                None => c.restrictor,
+                Some(existing) => self.conjunction(&c, &existing)?,
+            };
+            declared.insert((c.origin, c.target), restrictor);
+        }
+
+        for &(origin, target) in symmetric_pairs.iter() {
+            self.check_symmetric(&declared, origin, target)?;
+        }
+
+        let mut table = ConstraintTable::new(self.keys().count());
+        for ((origin, target), restrictor) in declared.into_iter() {
+            table.set(origin, target, restrictor);
+        }
+        Ok(table)
+    }
 
-    fn read(&self, repr: Self::Repr)
-        -> Result<Table<Key, Table<Key, Restrictor>>>
+    /// Read an all-different group. The names may be patterns.
+    pub fn read_group(&self, names: Vec<&'s str>) -> Result<Vec<Num<Key>>> {
+        let mut keys = Vec::new();
+        for name in names.into_iter() {
+            keys.extend(self.read_keys(name)?);
+        }
+        for (i, key_num) in keys.iter().enumerate() {
+            if keys[..i].contains(key_num) {
+                bail!("key {} appears twice in an all-different group",
+                      self.keys()[*key_num]);
+            }
+        }
+        Ok(keys)
+    }
+}
+
+impl<'s> NameReader<'s> {
+    /// Combine a constraint with an earlier one on the same pair of keys.
+    /// Fails when, for some origin value, the two leave no value for the
+    /// target that they both allow.
+    fn conjunction(&self, c: &Constraint, existing: &Restrictor)
+        -> Result<Restrictor>
     {
-        // construct an empty constraint table
-        let mut table = self.keys().map_nums(|_| {
-            self.keys().map_nums(|_| None)
-        });
+        let mut restrictor = existing.clone();
+        for (value_num, restriction) in c.restrictor.enumerate() {
+            let combined = restriction.intersect(&existing[value_num]);
+            if self.allows_none(&combined) &&
+                !self.allows_none(restriction) &&
+                !self.allows_none(&existing[value_num])
+            {
+                bail!("constraints from {} on {} contradict each other when \
+                       {} is {}",
+                      self.keys()[c.origin], self.keys()[c.target],
+                      self.keys()[c.origin], self.values()[value_num]);
+            }
+            restrictor[value_num] = combined;
+        }
+        Ok(restrictor)
+    }
 
+    /// Check that the constraints between two keys agree on which pairs of
+    /// values are allowed, so that it does not matter which key is assigned
+    /// first.
+    fn check_symmetric(&self,
+                       declared: &HashMap<(Num<Key>, Num<Key>), Restrictor>,
+                       origin: Num<Key>,
+                       target: Num<Key>)
+                       -> Result<()>
+    {
+        let forward = declared.get(&(origin, target));
+        let backward = declared.get(&(target, origin));
+        let (forward, backward) = match (forward, backward) {
+            (Some(forward), Some(backward)) => (forward, backward),
+            _ => return Ok(()),
+        };
 
-        let constraints: Vec<Constraint> = self.read_vec(repr)?;
-        for c in constraints.into_iter() {
-            table[c.origin][c.target] = Some(c.restrictor);
-        }
-        
-        return Ok(table.map_into(|row| {
-            row.map_into(|entry| {
-                match entry {
-                    Some(restrictor) => restrictor,
-                    None => self.values().map_nums(|_| {
-                        Restriction::Not(vec![])
-                    })
+        for v in self.values().nums() {
+            for w in self.values().nums() {
+                if forward[v].allows(w) != backward[w].allows(v) {
+                    bail!("symmetric constraint between {} and {} is not \
+                           consistent: {} = {} and {} = {} are allowed in one \
+                           direction only",
+                          self.keys()[origin], self.keys()[target],
+                          self.keys()[origin], self.values()[v],
+                          self.keys()[target], self.values()[w]);
                 }
-            })
-        }));
+            }
+        }
+        Ok(())
+    }
+
+    fn allows_none(&self, restriction: &Restriction) -> bool {
+        !self.values().nums().any(|value_num| restriction.allows(value_num))
     }
 }
 
+/// Composed restrictions are reduced to a plain `Not` or `Only` restriction
+/// while reading, so that the search only has to deal with those.
 impl<'s> Reader<Restriction> for NameReader<'s> {
     type Repr = RestrictionData<'s>;
 
     fn read(&self, repr: RestrictionData<'s>) -> Result<Restriction> {
         Ok(match repr {
             RestrictionData::Not(values) => {
-                Restriction::Not(self.read_vec(values)?)
+                Restriction::Not(self.read_value_list(&values)?)
             },
             RestrictionData::Only(values) => {
-                Restriction::Only(self.read_vec(values)?)
+                Restriction::Only(self.read_value_list(&values)?)
+            }
+            RestrictionData::Range(first, last) => {
+                let first: Num<Value> = self.read(first)?;
+                let last: Num<Value> = self.read(last)?;
+                if first > last {
+                    bail!("empty value range: {} comes after {}",
+                          self.values()[first], self.values()[last]);
+                }
+                Restriction::Only(self.values().nums()
+                    .filter(|&value_num| first <= value_num && value_num <= last)
+                    .collect())
+            }
+            RestrictionData::AllOf(parts) => {
+                let parts: Vec<Restriction> = self.read_vec(parts)?;
+                parts.iter().fold(Restriction::Not(vec![]), |acc, part| {
+                    acc.intersect(part)
+                })
+            }
+            RestrictionData::AnyOf(parts) => {
+                let parts: Vec<Restriction> = self.read_vec(parts)?;
+                parts.iter().fold(Restriction::Only(vec![]), |acc, part| {
+                    acc.union(part)
+                })
+            }
+            RestrictionData::Except(restriction) => {
+                let restriction: Restriction = self.read(*restriction)?;
+                restriction.inverse()
             }
         })
     }
 }
 
 impl<'s> Reader<Restrictor> for NameReader<'s> {
-    type Repr = HashMap<&'s str, RestrictionData<'s>>;
+    type Repr = BTreeMap<&'s str, RestrictionData<'s>>;
 
     fn read(&self, repr: Self::Repr) -> Result<Restrictor> {
         let mut tbl = self.values().map_nums(|_| Restriction::Not(Vec::new()));
-        for (value_name, restriction_repr) in repr.into_iter() {
-            let value_num = self.read(value_name)?;
-            let restriction = self.read(restriction_repr)?;
-            tbl[value_num] = restriction;
+        for (value_pattern, restriction_repr) in repr.into_iter() {
+            let restriction: Restriction = self.read(restriction_repr)?;
+            // patterns may overlap; all restrictions for a value have to hold
+            for value_num in self.read_values(value_pattern)? {
+                tbl[value_num] = tbl[value_num].intersect(&restriction);
+            }
         }
         Ok(tbl)
     }
 }
 
-impl<'s> Reader<KeyRestriction> for NameReader<'s> {
+/// A key restriction applies to every key its key pattern matches.
+impl<'s> Reader<Vec<KeyRestriction>> for NameReader<'s> {
     type Repr = KeyRestrictionData<'s>;
 
-    fn read(&self, repr: KeyRestrictionData<'s>) -> Result<KeyRestriction> {
-        Ok(KeyRestriction {
-            key: self.read(repr.key)?,
-            restriction: self.read(repr.restriction)?,
-        })
+    fn read(&self, repr: KeyRestrictionData<'s>) -> Result<Vec<KeyRestriction>> {
+        let restriction: Restriction = self.read(repr.restriction)?;
+        Ok(self.read_keys(repr.key)?.into_iter().map(|key_num| {
+            KeyRestriction {
+                key: key_num,
+                restriction: restriction.clone(),
+            }
+        }).collect())
     }
 }
 
-impl<'s> Reader<Constraint> for NameReader<'s> {
+/// A constraint holds between every origin and target its patterns match,
+/// except between a key and itself.
+impl<'s> Reader<Vec<Constraint>> for NameReader<'s> {
     type Repr = ConstraintData<'s>;
 
-    fn read(&self, repr: ConstraintData<'s>) -> Result<Constraint> {
-        Ok(Constraint {
-            origin: self.read(repr.origin)?,
-            target: self.read(repr.target)?,
-            restrictor: self.read(repr.restrictor)?,
-        })
+    fn read(&self, repr: ConstraintData<'s>) -> Result<Vec<Constraint>> {
+        let origins = self.read_keys(repr.origin)?;
+        let targets = self.read_keys(repr.target)?;
+        let restrictor: Restrictor = self.read(repr.restrictor)?;
+
+        let mut constraints = Vec::new();
+        for &origin in origins.iter() {
+            for &target in targets.iter().filter(|&&t| t != origin) {
+                constraints.push(Constraint {
+                    origin,
+                    target,
+                    restrictor: restrictor.clone(),
+                });
+            }
+        }
+        Ok(constraints)
     }
-}
\ No newline at end of file
+}
+impl<'s> Reader<Vec<Cardinality>> for NameReader<'s> {
+    type Repr = CardinalityData<'s>;
+
+    fn read(&self, repr: CardinalityData<'s>) -> Result<Vec<Cardinality>> {
+        let values = self.read_value_list(&repr.values)?;
+        if repr.max.is_some_and(|max| max < repr.min) {
+            bail!("cardinality of {} has a minimum above its maximum",
+                  repr.values.join(", "));
+        }
+        Ok(values.into_iter().map(|value_num| {
+            Cardinality { value: value_num, min: repr.min, max: repr.max }
+        }).collect())
+    }
+}
+
+impl<'s> Reader<Vec<Num<Key>>> for NameReader<'s> {
+    type Repr = AllDifferentData<'s>;
+
+    fn read(&self, repr: AllDifferentData<'s>) -> Result<Vec<Num<Key>>> {
+        self.read_group(repr.0)
+    }
+}
@@ -1,18 +1,634 @@
 mod domain;
 mod utils;
 mod reader;
+mod nogoods;
+mod scores;
+mod sources;
+mod templates;
+mod keymap;
+mod report;
+mod trace;
 
 
-use data::Domain;
+use cat::*;
+use data::{Assignment, Domain, Key, Lint, Nogood, Value};
 use Result;
 
+use serde_json;
+use std::fs::File;
+use std::io::Write;
+
 use self::utils::JsonBuffer;
-use self::reader::Reader;
+use self::reader::{Reader, NameReader};
 use self::domain::DomainData;
+use self::nogoods::NogoodsData;
+use self::scores::ScoresData;
+use self::keymap::KeymapData;
+use self::report::ReportData;
+
+pub use self::report::{Outcome, Report};
+pub use self::sources::{Source, Sources};
+pub use self::trace::JsonTrace;
 
 
 pub fn read_config(path: &str) -> Result<Domain> {
     JsonBuffer::from_file(path).map(|data: DomainData| {
-        data.mk_name_reader().read(data)
+        data.mk_name_reader()?.read(data)
+    })
+}
+
+/// Read a domain, along with the parts of the file each of its entries was
+/// read from.
+pub fn read_config_with_sources(path: &str) -> Result<(Domain, Sources)> {
+    JsonBuffer::from_file(path).map(|data: DomainData| {
+        data.mk_name_reader()?.read(data)
+    })
+}
+
+/// Read a domain, and look for suspicious parts in both the declarations in
+/// the file and the resulting domain.
+pub fn lint_config(path: &str) -> Result<Vec<Lint>> {
+    JsonBuffer::from_file(path).map(|data: DomainData| {
+        let reader = data.mk_name_reader()?;
+        let mut lints = data.lint(&reader)?;
+        let domain: Domain = reader.read(data)?;
+        lints.extend(domain.lint());
+        Ok(lints)
     })
 }
+
+/// Read the nogoods stored in a file. Returns None when they were learned on
+/// a different domain. Only a domain that restricts the values of its keys
+/// further counts as the same: any other change may invalidate nogoods on
+/// keys it does not mention, so they are all dropped.
+pub fn read_nogoods(path: &str, domain: &Domain) -> Result<Option<Vec<Nogood>>> {
+    JsonBuffer::from_file(path).map(|data: NogoodsData| {
+        if !data.matches(domain) {
+            return Ok(None);
+        }
+        NameReader::for_domain(domain).read(data).map(Some)
+    })
+}
+
+/// Read value preference scores for the keys of a domain.
+pub fn read_scores(path: &str, domain: &Domain)
+    -> Result<Table<Key, Table<Value, f64>>>
+{
+    JsonBuffer::from_file(path).map(|data: ScoresData| {
+        NameReader::for_domain(domain).read(data)
+    })
+}
+
+/// Read the keys to pin from a file mapping key names to value names.
+pub fn read_pins(path: &str, domain: &Domain) -> Result<Vec<Assignment>> {
+    JsonBuffer::from_file(path).map(|data: KeymapData| {
+        NameReader::for_domain(domain).read(data)
+    })
+}
+
+/// Resolve pins given as pairs of key and value names.
+pub fn resolve_pins(pins: &[(String, String)], domain: &Domain)
+    -> Result<Vec<Assignment>>
+{
+    let data = KeymapData::from_pairs(pins.iter().map(|(key, value)| {
+        (key.as_str(), value.as_str())
+    }))?;
+    NameReader::for_domain(domain).read(data)
+}
+
+pub fn write_nogoods(path: &str, domain: &Domain, nogoods: &[Nogood])
+    -> Result<()>
+{
+    let file = File::create(path)?;
+    let data = NogoodsData::from_nogoods(domain, nogoods);
+    serde_json::to_writer_pretty(file, &data)?;
+    Ok(())
+}
+
+/// Write the given parts of a domain file, in the format of a domain file.
+pub fn write_sources<W>(out: W, path: &str, sources: &[Source]) -> Result<()>
+    where W: Write
+{
+    let data = JsonBuffer::from_file(path).map(|domain: serde_json::Value| {
+        Ok(sources::select(&domain, sources))
+    })?;
+    serde_json::to_writer_pretty(out, &data)?;
+    Ok(())
+}
+
+/// Write the results of solving a domain as JSON.
+pub fn write_report<W>(out: W, domain: &Domain, report: &Report) -> Result<()>
+    where W: Write
+{
+    let data = ReportData::from_report(domain, report);
+    serde_json::to_writer_pretty(out, &data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use algorithm::{self, Backtracker, SearchResult};
+    use data::Restriction;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    static NUM_FILES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Run f on a file with the given contents. Every call gets a file of
+    /// its own, so tests running in parallel do not overwrite each other's.
+    fn with_file<F, R>(name: &str, contents: &str, f: F) -> R
+        where F: FnOnce(&str) -> R
+    {
+        let file_num = NUM_FILES.fetch_add(1, Ordering::SeqCst);
+        let name = format!("{}_{}_{}", process::id(), file_num, name);
+        let path = env::temp_dir().join(name);
+        fs::write(&path, contents).unwrap();
+        let result = f(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    /// A domain with two constraints from x on y, the second one having the
+    /// given restrictor.
+    fn two_constraints(restrictor: &str) -> String {
+        format!(r#"{{
+            "keys": ["x", "y"],
+            "values": ["a", "b", "c"],
+            "restrictions": [],
+            "constraints": [
+                {{ "origin": "x", "target": "y", "restrictor": {{ "a": {{ "not": ["a"] }} }} }},
+                {{ "origin": "x", "target": "y", "restrictor": {} }}
+            ]
+        }}"#, restrictor)
+    }
+
+    #[test]
+    fn test_conjunction() {
+        let contents = two_constraints(r#"{ "a": { "only": ["a", "b"] } }"#);
+        let domain = with_file("layoutgen_conjunction.json", &contents, |path| {
+            read_config(path)
+        }).unwrap();
+
+        let keys: Vec<Num<Key>> = domain.keys.nums().collect();
+        let values: Vec<Num<Value>> = domain.values.nums().collect();
+        let (x, y) = (keys[0], keys[1]);
+        let restrictor = domain.constraint_table.get(x, y).unwrap();
+        assert_eq!(restrictor[values[0]], Restriction::Only(vec![values[1]]));
+        assert!(restrictor[values[1]].is_trivial());
+
+        let lints = with_file("layoutgen_conjunction.json", &contents, |path| {
+            lint_config(path)
+        }).unwrap();
+        assert_eq!(lints, [
+            Lint::DuplicateConstraint {
+                origin: "x".to_string(),
+                target: "y".to_string(),
+                declarations: vec![0, 1],
+            },
+            Lint::OneDirectional {
+                origin: "x".to_string(),
+                target: "y".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_contradiction() {
+        // x = a leaves nothing for y
+        let contents = two_constraints(r#"{ "a": { "only": ["a"] } }"#);
+        let result = with_file("layoutgen_contradiction.json", &contents, |path| {
+            read_config(path)
+        });
+        assert!(result.is_err());
+    }
+
+    fn symmetric_domain(reverse: &str) -> String {
+        format!(r#"{{
+            "keys": ["x", "y"],
+            "values": ["a", "b"],
+            "restrictions": [],
+            "constraints": [
+                {{
+                    "origin": "x",
+                    "target": "y",
+                    "restrictor": {{ "a": {{ "not": ["a"] }} }},
+                    "symmetric": true
+                }}
+                {}
+            ]
+        }}"#, reverse)
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let contents = symmetric_domain("");
+        let domain = with_file("layoutgen_symmetric.json", &contents, |path| {
+            read_config(path)
+        }).unwrap();
+
+        let keys: Vec<Num<Key>> = domain.keys.nums().collect();
+        let values: Vec<Num<Value>> = domain.values.nums().collect();
+        let (x, y) = (keys[0], keys[1]);
+        let backward = domain.constraint_table.get(y, x).unwrap();
+        assert_eq!(backward[values[0]], Restriction::Not(vec![values[0]]));
+        assert!(backward[values[1]].is_trivial());
+
+        // declaring the inverse explicitly as well is fine
+        let contents = symmetric_domain(r#", {
+            "origin": "y",
+            "target": "x",
+            "restrictor": { "a": { "not": ["a"] } }
+        }"#);
+        let result = with_file("layoutgen_symmetric.json", &contents, |path| {
+            read_config(path)
+        });
+        assert!(result.is_ok());
+
+        // but a reverse constraint that disagrees is not
+        let contents = symmetric_domain(r#", {
+            "origin": "y",
+            "target": "x",
+            "restrictor": { "b": { "not": ["b"] } }
+        }"#);
+        let result = with_file("layoutgen_symmetric.json", &contents, |path| {
+            read_config(path)
+        });
+        assert!(result.is_err());
+    }
+
+    fn count_solutions(contents: &str) -> Result<usize> {
+        let domain = with_file("layoutgen_templates.json", contents, |path| {
+            read_config(path)
+        })?;
+        return Ok(Backtracker::new(&domain).count_solutions());
+    }
+
+    #[test]
+    fn test_templates() {
+        // abcABC, written with templates
+        let contents = r#"{
+            "keys": ["1/lower", "2/lower", "3/lower", "1/upper", "2/upper", "3/upper"],
+            "values": ["a", "b", "c", "A", "B", "C"],
+            "restrictions": [],
+            "constraints": [],
+            "templates": [
+                { "forbid": {
+                    "keys": ["1/lower", "2/lower", "3/lower"],
+                    "values": ["A", "B", "C"]
+                } },
+                { "distinct": { "keys": ["1/lower", "2/lower", "3/lower"] } },
+                { "same_key_pair": {
+                    "keys": [["1/lower", "1/upper"], ["2/lower", "2/upper"], ["3/lower", "3/upper"]],
+                    "values": [["a", "A"], ["b", "B"], ["c", "C"]]
+                } }
+            ]
+        }"#;
+        assert_eq!(count_solutions(contents).unwrap(), 6);
+
+        // of the nine ways to assign a, b and c to x and y, only a with b
+        // is ruled out
+        let contents = r#"{
+            "keys": ["x", "y"],
+            "values": ["a", "b", "c"],
+            "restrictions": [],
+            "constraints": [],
+            "templates": [
+                { "exclusive": { "keys": ["x", "y"], "values": ["a", "b"] } }
+            ]
+        }"#;
+        assert_eq!(count_solutions(contents).unwrap(), 7);
+
+        let contents = r#"{
+            "keys": ["x"],
+            "values": ["a", "b"],
+            "restrictions": [],
+            "constraints": [],
+            "templates": [
+                { "pin": { "key": "x", "value": "a" } },
+                { "forbid": { "keys": ["x"], "values": ["a"] } }
+            ]
+        }"#;
+        assert!(count_solutions(contents).is_err());
+    }
+
+    #[test]
+    fn test_restriction_order() {
+        // the last restriction on x is used, so x can be b or c
+        let contents = r#"{
+            "keys": ["x"],
+            "values": ["a", "b", "c"],
+            "restrictions": [
+                { "key": "x", "restriction": { "only": ["a"] } },
+                { "key": "x", "restriction": { "not": ["a"] } }
+            ],
+            "constraints": []
+        }"#;
+        assert_eq!(count_solutions(contents).unwrap(), 2);
+        let lints = with_file("layoutgen_restriction_order.json", contents, |path| {
+            lint_config(path)
+        }).unwrap();
+        assert_eq!(lints, [
+            Lint::DuplicateRestriction {
+                key: "x".to_string(),
+                declarations: vec![0, 1],
+            },
+            Lint::UnusedValue { value: "a".to_string() },
+        ]);
+
+        // the templates restrict x further
+        let contents = r#"{
+            "keys": ["x"],
+            "values": ["a", "b", "c"],
+            "restrictions": [
+                { "key": "x", "restriction": { "not": ["a"] } }
+            ],
+            "constraints": [],
+            "templates": [
+                { "forbid": { "keys": ["x"], "values": ["b"] } }
+            ]
+        }"#;
+        assert_eq!(count_solutions(contents).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_sources() {
+        // x has to be a, so y cannot be a; the template leaves it nothing else
+        let contents = r#"{
+            "keys": ["x", "y", "z"],
+            "values": ["a", "b", "c"],
+            "restrictions": [
+                { "key": "z", "restriction": { "only": ["c"] } },
+                { "key": "x", "restriction": { "only": ["a"] } },
+                { "key": "y", "restriction": { "only": ["a", "b"] } }
+            ],
+            "constraints": [
+                { "origin": "x", "target": "y", "restrictor": { "a": { "not": ["a"] } } },
+                { "origin": "x", "target": "z", "restrictor": { "b": { "not": ["c"] } } }
+            ],
+            "templates": [
+                { "forbid": { "keys": ["y"], "values": ["b"] } }
+            ]
+        }"#;
+        let (domain, sources) = with_file("layoutgen_sources.json", contents, |path| {
+            read_config_with_sources(path)
+        }).unwrap();
+        let conflict = sources.of(&algorithm::explain(&domain).unwrap());
+        assert_eq!(conflict, [
+            Source::Restriction(1),
+            Source::Restriction(2),
+            Source::Constraint(0),
+            Source::Template(0),
+        ]);
+
+        let mut out = Vec::new();
+        with_file("layoutgen_sources.json", contents, |path| {
+            write_sources(&mut out, path, &conflict)
+        }).unwrap();
+        let selected: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(selected["restrictions"][0]["key"], "x");
+        assert_eq!(selected["restrictions"][1]["key"], "y");
+        assert_eq!(selected["constraints"].as_array().unwrap().len(), 1);
+        assert_eq!(selected["templates"][0]["forbid"]["keys"][0], "y");
+    }
+
+    #[test]
+    fn test_patterns() {
+        // abcABC, with patterns and sets in place of the key and value lists
+        let contents = r#"{
+            "keys": ["1/lower", "2/lower", "3/lower", "1/upper", "2/upper", "3/upper"],
+            "values": ["a", "b", "c", "A", "B", "C"],
+            "key_sets": { "lower": ["*/lower"] },
+            "value_sets": { "lower": ["a", "b", "c"], "upper": ["A", "B", "C"] },
+            "restrictions": [
+                { "key": "@lower", "restriction": { "only": ["@lower"] } },
+                { "key": "*/upper", "restriction": { "not": ["@lower"] } }
+            ],
+            "constraints": [
+                { "origin": "@lower", "target": "@lower",
+                  "restrictor": { "?": { "not": ["a"] }, "a": { "not": ["b", "c"] } } }
+            ],
+            "all_different": [["*/upper"]]
+        }"#;
+        // both restrictions hold for a, so a lower key with a leaves nothing
+        // for the others: the lower keys take b and c in any combination,
+        // and the upper keys are a permutation
+        assert_eq!(count_solutions(contents).unwrap(), 8 * 6);
+
+        // now the lower keys can also all get a
+        let contents = contents.replace(r#""?": { "not": ["a"] }, "#, "");
+        assert_eq!(count_solutions(&contents).unwrap(), 9 * 6);
+
+        let contents = contents.replace("*/upper", "*/middle");
+        let err = count_solutions(&contents).unwrap_err();
+        assert_eq!(err.to_string(), "pattern */middle matches no key");
+
+        let contents = contents.replace(r#""key": "@lower""#, r#""key": "@vowels""#);
+        let err = count_solutions(&contents).unwrap_err();
+        assert_eq!(err.to_string(), "unknown key set: @vowels");
+    }
+
+    fn restricted_count(restriction: &str) -> Result<usize> {
+        let contents = format!(r#"{{
+            "keys": ["x"],
+            "values": ["a", "b", "c", "d", "e", "f"],
+            "value_sets": {{ "letters": ["a", "b", "c", "d"], "vowels": ["a", "e"] }},
+            "restrictions": [{{ "key": "x", "restriction": {} }}],
+            "constraints": []
+        }}"#, restriction);
+        count_solutions(&contents)
+    }
+
+    #[test]
+    fn test_restriction_forms() {
+        // letters that are not vowels: b, c and d
+        let consonants = r#"{ "all_of": [
+            { "only": ["@letters"] },
+            { "except": { "only": ["@vowels"] } }
+        ] }"#;
+        assert_eq!(restricted_count(consonants).unwrap(), 3);
+
+        let range = r#"{ "range": ["b", "e"] }"#;
+        assert_eq!(restricted_count(range).unwrap(), 4);
+
+        let either = r#"{ "any_of": [
+            { "range": ["a", "b"] },
+            { "not": ["@letters"] },
+            { "only": ["a", "c"] }
+        ] }"#;
+        assert_eq!(restricted_count(either).unwrap(), 5);
+
+        assert_eq!(restricted_count(r#"{ "all_of": [] }"#).unwrap(), 6);
+        assert_eq!(restricted_count(r#"{ "any_of": [] }"#).unwrap(), 0);
+
+        let err = restricted_count(r#"{ "range": ["e", "b"] }"#).unwrap_err();
+        assert_eq!(err.to_string(), "empty value range: e comes after b");
+    }
+
+    #[test]
+    fn test_pins() {
+        let domain = read_config("abcABC.json").unwrap();
+        let contents = r#"{ "1/lower": "b", "2/upper": "C" }"#;
+        let pins = with_file("layoutgen_pins.json", contents, |path| {
+            read_pins(path, &domain)
+        }).unwrap();
+        let mut g = Backtracker::new(&domain);
+        g.pin(pins);
+        // 1/lower and 2/lower are fixed, which leaves a for 3/lower
+        assert_eq!(g.count_solutions(), 1);
+
+        let pins = [("1/lower".to_string(), "a".to_string()),
+                    ("1/lower".to_string(), "b".to_string())];
+        assert!(resolve_pins(&pins, &domain).is_err());
+        let pins = [("1/lower".to_string(), "z".to_string())];
+        assert!(resolve_pins(&pins, &domain).is_err());
+    }
+
+    #[test]
+    fn test_blank_and_optional() {
+        // four slots for three symbols, of which c may be left out
+        let contents = r#"{
+            "keys": ["1", "2", "3", "4"],
+            "values": ["a", "b", "c", "_"],
+            "blank": "_",
+            "optional": ["c"],
+            "restrictions": [],
+            "constraints": [],
+            "all_different": [["*"]]
+        }"#;
+        // a, b and c on different keys, with the remaining key empty: 24
+        // ways; a and b on two keys and the other two empty: 12 ways
+        assert_eq!(count_solutions(contents).unwrap(), 24 + 12);
+
+        let contents = contents.replace(r#""optional": ["c"],"#, r#""optional": [],"#);
+        assert_eq!(count_solutions(&contents).unwrap(), 24);
+
+        let contents = contents.replace(r#""blank": "_","#, r#""blank": "-","#);
+        assert!(count_solutions(&contents).is_err());
+    }
+
+    #[test]
+    fn test_cardinality() {
+        let contents = r#"{
+            "keys": ["1", "2", "3", "4"],
+            "values": ["a", "b", "space"],
+            "cardinality": [{ "values": ["space"], "min": 1, "max": 2 }],
+            "restrictions": [],
+            "constraints": [],
+            "all_different": [["*"]]
+        }"#;
+        // a and b once, and space on the other two keys
+        assert_eq!(count_solutions(contents).unwrap(), 12);
+
+        // or space on three keys, with a or b on the last one
+        let contents = contents.replace(r#""max": 2"#, r#""max": 3"#);
+        assert_eq!(count_solutions(&contents).unwrap(), 12 + 4 + 4);
+
+        let contents = contents.replace(r#""min": 1"#, r#""min": 4"#);
+        let err = count_solutions(&contents).unwrap_err();
+        assert_eq!(err.to_string(), "cardinality of space has a minimum above its maximum");
+    }
+
+    #[test]
+    fn test_nogoods() {
+        let domain = read_config("petersen.json").unwrap();
+        let key_num = domain.keys.nums().next().unwrap();
+        let red = domain.values.nums().next().unwrap();
+        let nogoods = vec![Nogood::new(vec![Assignment { key_num, value_num: red }])];
+
+        let path = env::temp_dir().join("layoutgen_nogoods.json");
+        let path = path.to_str().unwrap();
+        let read_back = |domain: &Domain| read_nogoods(path, domain).unwrap();
+        write_nogoods(path, &domain, &nogoods).unwrap();
+        assert_eq!(read_back(&domain), Some(nogoods.clone()));
+
+        // restricting a key further keeps the nogoods
+        let mut pinned = read_config("petersen.json").unwrap();
+        pinned.key_restrictions[key_num] = Restriction::Only(vec![red]);
+        assert_eq!(read_back(&pinned), Some(nogoods.clone()));
+
+        // but allowing more values for a key does not
+        write_nogoods(path, &pinned, &nogoods).unwrap();
+        assert_eq!(read_back(&pinned), Some(nogoods.clone()));
+        assert_eq!(read_back(&domain), None);
+
+        // and neither does changing a constraint, even one on other keys
+        write_nogoods(path, &domain, &nogoods).unwrap();
+        let mut changed = read_config("petersen.json").unwrap();
+        let (origin, target, _) = changed.constraint_table.iter()
+            .find(|&(origin, target, _)| origin != key_num && target != key_num)
+            .unwrap();
+        let trivial = changed.values.map_nums(|_| Restriction::Not(vec![]));
+        changed.constraint_table.set(origin, target, trivial);
+        assert_eq!(read_back(&changed), None);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_report() {
+        let domain = read_config("abcABC.json").unwrap();
+        let mut g = Backtracker::new(&domain);
+        let mut solutions = Vec::new();
+        while let SearchResult::Solution(mapping) = g.generate() {
+            solutions.push(mapping);
+        }
+
+        let mut stats = g.stats().clone();
+        stats.elapsed = Duration::from_millis(1500);
+        let report = Report {
+            domain: "abcABC.json",
+            outcome: Outcome::Sat,
+            solutions: &solutions[..2],
+            num_solutions: solutions.len(),
+            num_nogoods: g.nogoods().len(),
+            stats: &stats,
+        };
+        let mut out = Vec::new();
+        write_report(&mut out, &domain, &report).unwrap();
+
+        let data: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(data["domain"], "abcABC.json");
+        assert_eq!(data["status"], "sat");
+        assert_eq!(data["stats"]["solutions"], 6);
+        assert_eq!(data["stats"]["seconds"], 1.5);
+        assert_eq!(data["stats"]["max_depth"], 6);
+        let first = &data["solutions"][0];
+        assert_eq!(first.as_object().unwrap().len(), 6);
+        let lower = first["1/lower"].as_str().unwrap();
+        assert_eq!(first["1/upper"], lower.to_uppercase());
+
+        // a solution can be used to pin keys
+        let pins = with_file("layoutgen_report.json", &first.to_string(), |path| {
+            read_pins(path, &domain)
+        }).unwrap();
+        let mut g = Backtracker::new(&domain);
+        g.pin(pins);
+        assert_eq!(g.count_solutions(), 1);
+    }
+
+    #[test]
+    fn test_json_trace() {
+        let domain = read_config("abcABC.json").unwrap();
+        let mut out = Vec::new();
+        {
+            let mut g = Backtracker::new(&domain);
+            g.set_observer(Box::new(JsonTrace::new(&mut out, 3)));
+            assert_eq!(g.count_solutions(), 6);
+        }
+
+        let events: Vec<serde_json::Value> = out.split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(events[0]["event"], "assign");
+        let solutions: Vec<&serde_json::Value> = events.iter()
+            .filter(|event| event["event"] == "solution")
+            .collect();
+        assert_eq!(solutions.len(), 6);
+        assert_eq!(solutions[0]["mapping"].as_object().unwrap().len(), 6);
+    }
+}
@@ -0,0 +1,105 @@
+use cat::*;
+use data::*;
+
+use Result;
+use super::reader::*;
+
+use std::collections::BTreeMap;
+
+/// Nogoods learned on a domain, stored by name so that they stay readable.
+///
+/// A nogood can follow from any constraint of the domain, not just from the
+/// ones on its own keys, so the nogoods are only kept for domains with the
+/// same constraints. Restricting the values of keys further is the one
+/// change that keeps them: it only removes solutions, so what had no
+/// solution still has none.
+#[derive(Serialize, Deserialize)]
+pub struct NogoodsData<'s> {
+    /// constraint fingerprint of the domain the nogoods were learned on
+    domain: String,
+    /// the values allowed for the keys that did not allow every value
+    #[serde(borrow, default)]
+    allowed: BTreeMap<&'s str, Vec<&'s str>>,
+    #[serde(borrow)]
+    nogoods: Vec<Vec<AssignmentData<'s>>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AssignmentData<'s> {
+    key: &'s str,
+    value: &'s str,
+}
+
+pub fn fingerprint_string(domain: &Domain) -> String {
+    format!("{:016x}", domain.constraint_fingerprint())
+}
+
+/// The values key_num allows in a domain.
+fn allowed_values<'a>(domain: &'a Domain, key_num: Num<Key>)
+    -> impl Iterator<Item = &'a str> + 'a
+{
+    domain.values.nums()
+        .filter(move |&value_num| domain.key_restrictions[key_num].allows(value_num))
+        .map(move |value_num| domain.values[value_num].as_str())
+}
+
+impl<'s> NogoodsData<'s> {
+    pub fn from_nogoods(domain: &'s Domain, nogoods: &[Nogood]) -> Self {
+        let num_values = domain.values.count().as_usize();
+        NogoodsData {
+            domain: fingerprint_string(domain),
+            allowed: domain.keys.nums().filter_map(|key_num| {
+                let allowed: Vec<&str> = allowed_values(domain, key_num).collect();
+                if allowed.len() == num_values {
+                    return None;
+                }
+                Some((domain.keys[key_num].as_str(), allowed))
+            }).collect(),
+            nogoods: nogoods.iter().map(|nogood| {
+                nogood.assignments().iter().map(|a| AssignmentData {
+                    key: &domain.keys[a.key_num],
+                    value: &domain.values[a.value_num],
+                }).collect()
+            }).collect(),
+        }
+    }
+
+    /// Whether these nogoods hold for given domain: it has to have the same
+    /// constraints as the domain they were learned on, and allow no values
+    /// for its keys that that domain did not allow.
+    pub fn matches(&self, domain: &Domain) -> bool {
+        if self.domain != fingerprint_string(domain) {
+            return false;
+        }
+        domain.keys.nums().all(|key_num| {
+            match self.allowed.get(domain.keys[key_num].as_str()) {
+                None => true,
+                Some(allowed) => allowed_values(domain, key_num).all(|value| {
+                    allowed.contains(&value)
+                }),
+            }
+        })
+    }
+}
+
+impl<'s> Reader<Vec<Nogood>> for NameReader<'s> {
+    type Repr = NogoodsData<'s>;
+
+    fn read(&self, repr: NogoodsData<'s>) -> Result<Vec<Nogood>> {
+        repr.nogoods.into_iter().map(|assignments| {
+            let assignments = self.read_vec(assignments)?;
+            Ok(Nogood::new(assignments))
+        }).collect()
+    }
+}
+
+impl<'s> Reader<Assignment> for NameReader<'s> {
+    type Repr = AssignmentData<'s>;
+
+    fn read(&self, repr: AssignmentData<'s>) -> Result<Assignment> {
+        Ok(Assignment {
+            key_num: self.read(repr.key)?,
+            value_num: self.read(repr.value)?,
+        })
+    }
+}
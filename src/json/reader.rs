@@ -1,7 +1,8 @@
 use std::collections::HashMap;
 
 use cat::*;
-use data::{Key, Value};
+use cat::ops::*;
+use data::{Domain, Key, Value};
 
 use Result;
 
@@ -20,6 +21,9 @@ pub struct NameReader<'s> {
     key_map: HashMap<&'s str, Num<Key>>,
     values: Table<Value, &'s str>,
     value_map: HashMap<&'s str, Num<Value>>,
+    // named sets, referred to as @name
+    key_sets: HashMap<&'s str, Vec<Num<Key>>>,
+    value_sets: HashMap<&'s str, Vec<Num<Value>>>,
 }
 
 impl<'s> NameReader<'s> {
@@ -30,20 +34,169 @@ impl<'s> NameReader<'s> {
         NameReader {
             key_map: mk_name_map(&keys),
             value_map: mk_name_map(&values),
-            keys: keys,
-            values: values,
+            keys,
+            values,
+            key_sets: HashMap::new(),
+            value_sets: HashMap::new(),
         }
     }
 
-    pub fn keys<'a>(&'a self) -> &'a Table<Key, &'a str> {
+    /// Define a named set of keys. Its members may be patterns, but not
+    /// other sets.
+    pub fn add_key_set(&mut self, name: &'s str, members: Vec<&'s str>)
+        -> Result<()>
+    {
+        let keys = resolve_set(name, members, |member| self.read_keys(member))?;
+        self.key_sets.insert(name, keys);
+        Ok(())
+    }
+
+    /// Define a named set of values. Its members may be patterns, but not
+    /// other sets.
+    pub fn add_value_set(&mut self, name: &'s str, members: Vec<&'s str>)
+        -> Result<()>
+    {
+        let values = resolve_set(name, members, |member| self.read_values(member))?;
+        self.value_sets.insert(name, values);
+        Ok(())
+    }
+
+    /// The keys a name refers to: an exact key name, a glob pattern using
+    /// `*` and `?`, or a key set written as `@name`.
+    pub fn read_keys(&self, pattern: &str) -> Result<Vec<Num<Key>>> {
+        resolve(pattern, &self.keys, &self.key_map, &self.key_sets, "key")
+    }
+
+    /// The values a name refers to, like `read_keys`.
+    pub fn read_values(&self, pattern: &str) -> Result<Vec<Num<Value>>> {
+        resolve(pattern, &self.values, &self.value_map, &self.value_sets, "value")
+    }
+
+    /// All keys the given names refer to, without duplicates.
+    pub fn read_key_list(&self, patterns: &[&str]) -> Result<Vec<Num<Key>>> {
+        let mut keys = Vec::new();
+        for pattern in patterns.iter() {
+            for key_num in self.read_keys(pattern)? {
+                if !keys.contains(&key_num) {
+                    keys.push(key_num);
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    /// All values the given names refer to, without duplicates.
+    pub fn read_value_list(&self, patterns: &[&str])
+        -> Result<Vec<Num<Value>>>
+    {
+        let mut values = Vec::new();
+        for pattern in patterns.iter() {
+            for value_num in self.read_values(pattern)? {
+                if !values.contains(&value_num) {
+                    values.push(value_num);
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Reader for names in an already parsed domain.
+    pub fn for_domain(domain: &'s Domain) -> Self {
+        NameReader::new(
+            domain.keys.map(|name| name.as_str()),
+            domain.values.map(|name| name.as_str())
+        )
+    }
+
+    pub fn keys(&self) -> &Table<Key, &str> {
         &self.keys
     }
 
-    pub fn values<'a>(&'a self) -> &'a Table<Value, &'a str> {
+    pub fn values(&self) -> &Table<Value, &str> {
         &self.values
     }
 }
 
+fn resolve_set<D, F>(name: &str, members: Vec<&str>, read: F)
+    -> Result<Vec<Num<D>>>
+    where F: Fn(&str) -> Result<Vec<Num<D>>>
+{
+    let mut set = Vec::new();
+    for member in members.into_iter() {
+        if member.starts_with('@') {
+            bail!("set @{} refers to another set: {}", name, member);
+        }
+        for num in read(member)? {
+            if !set.contains(&num) {
+                set.push(num);
+            }
+        }
+    }
+    Ok(set)
+}
+
+/// Look up the elements a name refers to. Exact names take precedence over
+/// patterns.
+fn resolve<D>(pattern: &str,
+              names: &Table<D, &str>,
+              name_map: &HashMap<&str, Num<D>>,
+              sets: &HashMap<&str, Vec<Num<D>>>,
+              kind: &str)
+              -> Result<Vec<Num<D>>>
+{
+    if let Some(&num) = name_map.get(pattern) {
+        return Ok(vec![num]);
+    }
+
+    if let Some(set_name) = pattern.strip_prefix('@') {
+        return match sets.get(set_name) {
+            Some(set) => Ok(set.clone()),
+            None => bail!("unknown {} set: {}", kind, pattern),
+        };
+    }
+
+    if !pattern.contains(['*', '?']) {
+        bail!("unknown {}: {}", kind, pattern);
+    }
+
+    let matches: Vec<Num<D>> = names.enumerate()
+        .filter(|&(_, name)| glob_matches(pattern, name))
+        .map(|(num, _)| num)
+        .collect();
+    if matches.is_empty() {
+        bail!("pattern {} matches no {}", pattern, kind);
+    }
+    Ok(matches)
+}
+
+/// Whether name matches a glob pattern, where `*` matches any sequence of
+/// characters and `?` any single character.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // position to resume from when the last star has to match one more
+    // character
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 fn mk_name_map<'a, D>(table: &Table<D, &'a str>) -> HashMap<&'a str, Num<D>> {
     table.enumerate().map(|(num, &name)| (name, num)).collect()
 }
@@ -71,4 +224,38 @@ impl<'s> Reader<Num<Value>> for NameReader<'s> {
             bail!("unknown value: {}", value_name)
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*/lower", "1/lower"));
+        assert!(glob_matches("*/lower", "/lower"));
+        assert!(!glob_matches("*/lower", "1/upper"));
+        assert!(glob_matches("?/*", "1/upper"));
+        assert!(!glob_matches("?/*", "10/upper"));
+        assert!(glob_matches("*a*b", "xaxxab"));
+        assert!(!glob_matches("*a*b", "xaxxa"));
+        assert!(glob_matches("**", ""));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let keys = Table::from_vec(vec!["1/lower", "2/lower", "1/upper"]);
+        let values = Table::from_vec(vec!["a", "e", "x"]);
+        let mut reader = NameReader::new(keys, values);
+        reader.add_value_set("vowels", vec!["a", "e"]).unwrap();
+
+        let lower = reader.read_keys("*/lower").unwrap();
+        assert_eq!(lower.len(), 2);
+        assert_eq!(reader.read_values("@vowels").unwrap().len(), 2);
+        assert_eq!(reader.read_value_list(&["@vowels", "a", "x"]).unwrap().len(), 3);
+
+        assert!(reader.read_keys("*/middle").is_err());
+        assert!(reader.read_values("@digits").is_err());
+        assert!(reader.read_values("y").is_err());
+        assert!(reader.add_value_set("all", vec!["@vowels"]).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use data::*;
+use cat::*;
+
+use algorithm::SearchStats;
+
+use super::keymap::KeymapData;
+
+/// How a search ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// At least one solution was found.
+    Sat,
+    /// The domain has no solution.
+    Unsat,
+    /// The search gave up at one of its limits before it was done.
+    Limit,
+}
+
+/// The results of solving a domain.
+pub struct Report<'a> {
+    /// path of the domain file
+    pub domain: &'a str,
+    pub outcome: Outcome,
+    /// the solutions to write out; empty when only counting
+    pub solutions: &'a [Table<Key, Num<Value>>],
+    pub num_solutions: usize,
+    pub num_nogoods: usize,
+    pub stats: &'a SearchStats,
+}
+
+#[derive(Serialize)]
+pub struct ReportData<'s> {
+    domain: &'s str,
+    status: Outcome,
+    #[serde(borrow)]
+    solutions: Vec<KeymapData<'s>>,
+    stats: StatsData,
+}
+
+#[derive(Serialize)]
+pub struct StatsData {
+    solutions: usize,
+    nodes: usize,
+    backtracks: usize,
+    wipeouts: usize,
+    restarts: usize,
+    max_depth: usize,
+    nogoods: usize,
+    seconds: f64,
+}
+
+impl<'s> ReportData<'s> {
+    pub fn from_report(domain: &'s Domain, report: &Report<'s>) -> Self {
+        ReportData {
+            domain: report.domain,
+            status: report.outcome,
+            solutions: report.solutions.iter().map(|mapping| {
+                KeymapData::from_mapping(domain, mapping)
+            }).collect(),
+            stats: StatsData {
+                solutions: report.num_solutions,
+                nodes: report.stats.nodes,
+                backtracks: report.stats.backtracks,
+                wipeouts: report.stats.wipeouts,
+                restarts: report.stats.restarts,
+                max_depth: report.stats.max_depth,
+                nogoods: report.num_nogoods,
+                seconds: report.stats.elapsed.as_secs_f64(),
+            },
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use data::*;
+use cat::*;
+use std::collections::HashMap;
+
+use Result;
+use super::reader::*;
+
+/// Preference scores for assigning values to keys, by name:
+/// `{ "key": { "value": score, ... }, ... }`.
+#[derive(Deserialize)]
+pub struct ScoresData<'s>(#[serde(borrow)] HashMap<&'s str, HashMap<&'s str, f64>>);
+
+impl<'s> Reader<Table<Key, Table<Value, f64>>> for NameReader<'s> {
+    type Repr = ScoresData<'s>;
+
+    fn read(&self, repr: ScoresData<'s>) -> Result<Table<Key, Table<Value, f64>>> {
+        let mut scores = self.keys().map_nums(|_| {
+            self.values().map_nums(|_| 0.0)
+        });
+        for (key_name, value_scores) in repr.0.into_iter() {
+            let key_num: Num<Key> = self.read(key_name)?;
+            for (value_name, score) in value_scores.into_iter() {
+                let value_num: Num<Value> = self.read(value_name)?;
+                scores[key_num][value_num] = score;
+            }
+        }
+        Ok(scores)
+    }
+}
@@ -0,0 +1,95 @@
+use data::Entry;
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value};
+
+/// A part of a domain file: an element of one of its lists, by index, or
+/// its list of optional values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    Restriction(usize),
+    Constraint(usize),
+    AllDifferent(usize),
+    Template(usize),
+    Cardinality(usize),
+    Optional,
+}
+
+impl Source {
+    /// The name of the list in the domain file this part is in.
+    fn section(&self) -> &'static str {
+        match self {
+            Source::Restriction(_) => "restrictions",
+            Source::Constraint(_) => "constraints",
+            Source::AllDifferent(_) => "all_different",
+            Source::Template(_) => "templates",
+            Source::Cardinality(_) => "cardinality",
+            Source::Optional => "optional",
+        }
+    }
+}
+
+/// The parts of a domain file that each entry of the domain was read from.
+#[derive(Debug, Default)]
+pub struct Sources {
+    sources: BTreeMap<Entry, Vec<Source>>,
+}
+
+impl Sources {
+    pub fn new() -> Self {
+        Sources::default()
+    }
+
+    /// Record that a part of the file contributes to an entry.
+    pub fn add(&mut self, entry: Entry, source: Source) {
+        let sources = self.sources.entry(entry).or_default();
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+
+    /// Record that a part of the file replaces what the entry was read from
+    /// so far.
+    pub fn replace(&mut self, entry: Entry, source: Source) {
+        self.sources.insert(entry, vec![source]);
+    }
+
+    /// The parts of the file the given entries were read from, in the order
+    /// of the file.
+    pub fn of(&self, entries: &[Entry]) -> Vec<Source> {
+        let mut sources: Vec<Source> = entries.iter()
+            .flat_map(|entry| self.sources.get(entry))
+            .flatten()
+            .cloned()
+            .collect();
+        sources.sort();
+        sources.dedup();
+        sources
+    }
+}
+
+/// Pick the given parts out of a domain file, keeping the format of a domain
+/// file.
+pub fn select(domain: &Value, sources: &[Source]) -> Value {
+    let mut data = Map::new();
+    for &source in sources.iter() {
+        let section = source.section();
+        let idx = match source {
+            Source::Restriction(idx) |
+            Source::Constraint(idx) |
+            Source::AllDifferent(idx) |
+            Source::Template(idx) |
+            Source::Cardinality(idx) => idx,
+            Source::Optional => {
+                data.insert(section.to_string(), domain[section].clone());
+                continue;
+            }
+        };
+        let selected = data.entry(section.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(items) = selected {
+            items.push(domain[section][idx].clone());
+        }
+    }
+    Value::Object(data)
+}
@@ -0,0 +1,158 @@
+use data::*;
+use cat::*;
+
+use Result;
+use super::reader::*;
+
+/// A common rule, which is expanded into restrictions, constraints and
+/// all-different groups when the domain is read.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateData<'s> {
+    /// For every pair of keys, the first key has the first value of a value
+    /// pair exactly when the second key has the second value, e.g. `a` and
+    /// `A` on the lower and upper layer of the same physical key.
+    #[serde(borrow)]
+    SameKeyPair(SameKeyPairData<'s>),
+    /// The keys all get a different value.
+    #[serde(borrow)]
+    Distinct(DistinctData<'s>),
+    /// The keys use at most one of the values between them, though that
+    /// value may be used more than once.
+    #[serde(borrow)]
+    Exclusive(ExclusiveData<'s>),
+    /// The key gets the value.
+    #[serde(borrow)]
+    Pin(PinData<'s>),
+    /// None of the keys gets any of the values.
+    #[serde(borrow)]
+    Forbid(ForbidData<'s>),
+}
+
+#[derive(Deserialize)]
+pub struct SameKeyPairData<'s> {
+    #[serde(borrow)]
+    keys: Vec<(&'s str, &'s str)>,
+    #[serde(borrow)]
+    values: Vec<(&'s str, &'s str)>,
+}
+
+#[derive(Deserialize)]
+pub struct DistinctData<'s> {
+    #[serde(borrow)]
+    keys: Vec<&'s str>,
+}
+
+#[derive(Deserialize)]
+pub struct ExclusiveData<'s> {
+    #[serde(borrow)]
+    keys: Vec<&'s str>,
+    #[serde(borrow)]
+    values: Vec<&'s str>,
+}
+
+#[derive(Deserialize)]
+pub struct PinData<'s> {
+    key: &'s str,
+    value: &'s str,
+}
+
+#[derive(Deserialize)]
+pub struct ForbidData<'s> {
+    #[serde(borrow)]
+    keys: Vec<&'s str>,
+    #[serde(borrow)]
+    values: Vec<&'s str>,
+}
+
+/// The rules a template stands for.
+pub struct Expansion {
+    pub restrictions: Vec<KeyRestriction>,
+    pub constraints: Vec<Constraint>,
+    pub all_different: Vec<Vec<Num<Key>>>,
+}
+
+impl Expansion {
+    fn new() -> Self {
+        Expansion {
+            restrictions: Vec::new(),
+            constraints: Vec::new(),
+            all_different: Vec::new(),
+        }
+    }
+}
+
+impl<'s> Reader<Expansion> for NameReader<'s> {
+    type Repr = TemplateData<'s>;
+
+    fn read(&self, repr: TemplateData<'s>) -> Result<Expansion> {
+        let mut expansion = Expansion::new();
+        match repr {
+            TemplateData::SameKeyPair(data) => {
+                for (first, second) in data.keys.into_iter() {
+                    let origin: Num<Key> = self.read(first)?;
+                    let target: Num<Key> = self.read(second)?;
+                    for &(x, y) in data.values.iter() {
+                        let x: Num<Value> = self.read(x)?;
+                        let y: Num<Value> = self.read(y)?;
+                        let c = Constraint {
+                            origin,
+                            target,
+                            restrictor: self.values().map_nums(|value_num| {
+                                if value_num == x {
+                                    Restriction::Only(vec![y])
+                                } else {
+                                    Restriction::Not(vec![y])
+                                }
+                            }),
+                        };
+                        expansion.constraints.push(c.inverse());
+                        expansion.constraints.push(c);
+                    }
+                }
+            }
+            TemplateData::Distinct(data) => {
+                expansion.all_different.push(self.read_group(data.keys)?);
+            }
+            TemplateData::Exclusive(data) => {
+                let keys = self.read_key_list(&data.keys)?;
+                let values = self.read_value_list(&data.values)?;
+                let restrictor = self.values().map_nums(|value_num| {
+                    if values.contains(&value_num) {
+                        let others = values.iter().cloned()
+                            .filter(|&other| other != value_num)
+                            .collect();
+                        Restriction::Not(others)
+                    } else {
+                        Restriction::Not(vec![])
+                    }
+                });
+                for &origin in keys.iter() {
+                    for &target in keys.iter().filter(|&&k| k != origin) {
+                        expansion.constraints.push(Constraint {
+                            origin,
+                            target,
+                            restrictor: restrictor.clone(),
+                        });
+                    }
+                }
+            }
+            TemplateData::Pin(data) => {
+                expansion.restrictions.push(KeyRestriction {
+                    key: self.read(data.key)?,
+                    restriction: Restriction::Only(vec![self.read(data.value)?]),
+                });
+            }
+            TemplateData::Forbid(data) => {
+                let values = self.read_value_list(&data.values)?;
+                for key_num in self.read_key_list(&data.keys)? {
+                    expansion.restrictions.push(KeyRestriction {
+                        key: key_num,
+                        restriction: Restriction::Not(values.clone()),
+                    });
+                }
+            }
+        }
+        Ok(expansion)
+    }
+}
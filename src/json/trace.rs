@@ -0,0 +1,72 @@
+use algorithm::{Event, SearchObserver};
+use data::*;
+
+use serde_json;
+use std::io::Write;
+
+use super::keymap::KeymapData;
+
+/// Writes the events up to a verbosity level as JSON, one object per line.
+pub struct JsonTrace<W> {
+    out: W,
+    verbosity: usize,
+}
+
+impl<W: Write> JsonTrace<W> {
+    pub fn new(out: W, verbosity: usize) -> Self {
+        JsonTrace { out, verbosity }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum EventData<'s> {
+    Assign { key: &'s str, value: &'s str },
+    Unassign { key: &'s str, value: &'s str },
+    Wipeout { key: &'s str, conflict: Vec<&'s str> },
+    Backtrack { depth: usize },
+    Restart,
+    Solution {
+        #[serde(borrow)]
+        mapping: KeymapData<'s>,
+    },
+}
+
+impl<'s> EventData<'s> {
+    fn from_event(domain: &'s Domain, event: &Event) -> Self {
+        match *event {
+            Event::Assign(a) => EventData::Assign {
+                key: &domain.keys[a.key_num],
+                value: &domain.values[a.value_num],
+            },
+            Event::Unassign(a) => EventData::Unassign {
+                key: &domain.keys[a.key_num],
+                value: &domain.values[a.value_num],
+            },
+            Event::Wipeout { key_num, conflict } => EventData::Wipeout {
+                key: &domain.keys[key_num],
+                conflict: conflict.iter()
+                    .map(|&key_num| domain.keys[key_num].as_str())
+                    .collect(),
+            },
+            Event::Backtrack { depth } => EventData::Backtrack { depth },
+            Event::Restart => EventData::Restart,
+            Event::Solution(mapping) => EventData::Solution {
+                mapping: KeymapData::from_mapping(domain, mapping),
+            },
+        }
+    }
+}
+
+impl<W: Write> SearchObserver for JsonTrace<W> {
+    fn notify(&mut self, domain: &Domain, event: &Event) {
+        if event.level() > self.verbosity {
+            return;
+        }
+        let data = EventData::from_event(domain, event);
+        // a trace that cannot be written should not stop the search
+        if serde_json::to_writer(&mut self.out, &data).is_ok() {
+            let _ = writeln!(self.out);
+        }
+    }
+}
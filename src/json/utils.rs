@@ -14,7 +14,7 @@ pub struct JsonBuffer<'s> {
 impl<'s> JsonBuffer<'s> {
     pub fn from_file(path: &'s str) -> Self {
         JsonBuffer {
-            path: path,
+            path,
             contents: String::new(),
         }
     }
@@ -31,6 +31,6 @@ impl<'s> JsonBuffer<'s> {
     {
         self.read_file()?;
         let json = serde_json::from_str(&self.contents)?;
-        return fun(json);
+        fun(json)
     }
 }
@@ -1,13 +1,20 @@
 #![allow(dead_code)]
 
 mod algorithm;
+mod binary;
+// the category module keeps its full interface, not all of which the solver
+// uses, and its hand-written impls on Copy types.
+#[allow(unused_imports, clippy::clone_on_copy, clippy::non_canonical_clone_impl,
+        clippy::non_canonical_partial_ord_impl)]
 mod cat;
+mod cli;
 mod data;
 mod json;
 
 
 extern crate rand;
 extern crate rand_distr;
+extern crate byteorder;
 
 #[macro_use]
 extern crate failure;
@@ -22,21 +29,53 @@ extern crate serde_json;
 extern crate proptest;
 
 
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::process;
 use std::result;
 use failure::ResultExt;
 
+use algorithm::{Backtracker, ParallelResult, ParallelSolver, SearchResult, SearchStats,
+                ValueHeuristic};
+use cat::*;
+use cli::{Command, Format};
+use data::{Assignment, Domain, Key, Value};
+
 type Result<T> = result::Result<T, failure::Error>;
 
-fn main() {
-    if let Err(ref e) = run() {
-        eprintln!("{}", pretty_error(&e));
+/// Outcome of a successful run.
+enum Status {
+    Success,
+    Unsatisfiable,
+    LimitReached,
+}
 
-        let backtrace = e.backtrace().to_string();
-        if !backtrace.trim().is_empty() {
-            eprintln!("{}", backtrace);
+impl Status {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Status::Success => 0,
+            Status::Unsatisfiable => 2,
+            Status::LimitReached => 3,
         }
+    }
+}
 
-        ::std::process::exit(1);
+fn main() {
+    match run() {
+        Ok(status) => process::exit(status.exit_code()),
+        Err(ref e) => {
+            eprintln!("{}", pretty_error(e));
+
+            let backtrace = e.backtrace().to_string();
+            if !backtrace.trim().is_empty() {
+                eprintln!("{}", backtrace);
+            }
+
+            process::exit(1);
+        }
     }
 }
 
@@ -51,9 +90,381 @@ fn pretty_error(err: &failure::Error) -> String {
     pretty
 }
 
-fn run() -> Result<()> {
-    let domain = json::read_config("abcABC.json")
-        .context("Could not parse domain")?;
-    let mut b = algorithm::Backtracker::new(&domain);
-    return b.generate();
-}
\ No newline at end of file
+fn run() -> Result<Status> {
+    match cli::parse_args(env::args().skip(1))? {
+        Command::Solve(args) => {
+            let domain = read_domain(&args.domain)?;
+            solve(&domain, &args)
+        }
+        Command::Check(args) => {
+            let domain = read_domain(&args.domain)?;
+            let mut b = Backtracker::new(&domain);
+            match b.generate() {
+                SearchResult::Solution(_) => {
+                    println!("satisfiable");
+                    Ok(Status::Success)
+                }
+                SearchResult::Exhausted => {
+                    println!("unsatisfiable");
+                    Ok(Status::Unsatisfiable)
+                }
+                SearchResult::LimitReached => unreachable!("no limits were set"),
+            }
+        }
+        Command::Explain(args) => {
+            let (domain, sources) = json::read_config_with_sources(&args.domain)
+                .context(format!("Could not parse domain {}", args.domain))?;
+            match algorithm::explain(&domain) {
+                None => {
+                    println!("satisfiable");
+                    Ok(Status::Success)
+                }
+                Some(entries) => {
+                    eprintln!("unsatisfiable; these parts of the domain conflict:");
+                    json::write_sources(io::stdout(), &args.domain, &sources.of(&entries))?;
+                    println!();
+                    Ok(Status::Unsatisfiable)
+                }
+            }
+        }
+        Command::Compile(args) => {
+            let path = binary::compile(&args.domain)
+                .context(format!("Could not compile domain {}", args.domain))?;
+            eprintln!("compiled to {}", path);
+            Ok(Status::Success)
+        }
+        Command::Inspect(args) => {
+            let domain = read_domain(&args.domain)?;
+            inspect(&domain);
+            Ok(Status::Success)
+        }
+        Command::Lint(args) => {
+            let lints = json::lint_config(&args.domain)
+                .context(format!("Could not parse domain {}", args.domain))?;
+            for lint in lints.iter() {
+                println!("{}", lint);
+            }
+            eprintln!("{} problems found", lints.len());
+            Ok(Status::Success)
+        }
+        Command::Help => {
+            print!("{}", cli::USAGE);
+            Ok(Status::Success)
+        }
+    }
+}
+
+fn solve(domain: &Domain, args: &cli::SolveArgs) -> Result<Status> {
+    let mut out = open_output(&args.output)?;
+
+    let scores = match args.value_order {
+        ValueHeuristic::Scores(ref path) => {
+            let scores = json::read_scores(path, domain)
+                .context(format!("Could not read scores from {}", path))?;
+            Some(scores)
+        }
+        _ => None,
+    };
+    // the heuristics, for the backtracker of every thread
+    let configure = |b: &mut Backtracker| {
+        if let Some(seed) = args.seed {
+            b.set_seed(seed);
+        }
+        b.set_restarts(args.restarts);
+        b.set_variable_order(args.var_order.build());
+        if let Some(ref scores) = scores {
+            b.set_value_order(Box::new(algorithm::ScoreGuided::new(scores.clone())));
+        } else if args.value_order == ValueHeuristic::LeastConstraining {
+            b.set_value_order(Box::new(algorithm::LeastConstraining));
+        }
+    };
+    let pins = read_pins(domain, args)?;
+
+    let mut num_solutions = 0;
+    // the solutions for the json report, which is written at the end
+    let mut solutions = Vec::new();
+    let mut record = |mapping: Table<Key, Num<Value>>| -> Result<bool> {
+        if !args.count_only {
+            match args.format {
+                Format::Text => {
+                    if num_solutions > 0 {
+                        writeln!(out)?;
+                    }
+                    write_solution(&mut out, domain, &mapping)?;
+                }
+                Format::Json => solutions.push(mapping),
+            }
+        }
+        num_solutions += 1;
+        Ok(args.max_solutions.is_none_or(|max| num_solutions < max))
+    };
+
+    let search = if args.threads > 1 {
+        search_parallel(domain, args, pins, &configure, &mut record)?
+    } else {
+        search_sequential(domain, args, pins, &configure, &mut record)?
+    };
+
+    // solutions found before a limit are still reported, but the search
+    // did not get to finish what was asked of it
+    let outcome = if search.limit_reached {
+        json::Outcome::Limit
+    } else if num_solutions == 0 {
+        json::Outcome::Unsat
+    } else {
+        json::Outcome::Sat
+    };
+
+    match args.format {
+        Format::Text => {
+            if args.count_only {
+                writeln!(out, "{}", num_solutions)?;
+            } else if args.max_solutions != Some(1) {
+                eprintln!("{} solutions", num_solutions);
+            }
+            match outcome {
+                json::Outcome::Sat => (),
+                json::Outcome::Unsat => eprintln!("unsatisfiable"),
+                json::Outcome::Limit => eprintln!("search limit reached"),
+            }
+        }
+        Format::Json => {
+            let report = json::Report {
+                domain: &args.domain,
+                outcome,
+                solutions: &solutions,
+                num_solutions,
+                num_nogoods: search.num_nogoods,
+                stats: &search.stats,
+            };
+            json::write_report(&mut out, domain, &report)?;
+            writeln!(out)?;
+        }
+    }
+
+    Ok(match outcome {
+        json::Outcome::Sat => Status::Success,
+        json::Outcome::Unsat => Status::Unsatisfiable,
+        json::Outcome::Limit => Status::LimitReached,
+    })
+}
+
+/// What solve needs to know about a finished search.
+struct Search {
+    limit_reached: bool,
+    stats: SearchStats,
+    num_nogoods: usize,
+}
+
+/// Pass solutions to record for as long as it asks for more.
+fn search_sequential<F>(domain: &Domain,
+                        args: &cli::SolveArgs,
+                        pins: Vec<Assignment>,
+                        configure: &F,
+                        record: &mut dyn FnMut(Table<Key, Num<Value>>) -> Result<bool>)
+                        -> Result<Search>
+    where F: Fn(&mut Backtracker)
+{
+    let mut b = Backtracker::new(domain);
+    configure(&mut b);
+    b.set_limits(args.limits);
+    match (&args.trace_file, args.trace) {
+        (Some(path), verbosity) => {
+            let file = File::create(path)
+                .context(format!("Could not create {}", path))?;
+            let out = io::BufWriter::new(file);
+            b.set_observer(Box::new(json::JsonTrace::new(out, verbosity.unwrap_or(3))));
+        }
+        (None, Some(verbosity)) => {
+            b.set_observer(Box::new(algorithm::Trace::to_stderr(verbosity)));
+        }
+        (None, None) => (),
+    }
+
+    b.pin(pins);
+
+    if let Some(ref path) = args.nogoods {
+        if Path::new(path).exists() {
+            let nogoods = json::read_nogoods(path, domain)
+                .context(format!("Could not read nogoods from {}", path))?;
+            match nogoods {
+                Some(nogoods) => b.add_nogoods(nogoods),
+                None => eprintln!("ignoring {}: the domain has changed since", path),
+            }
+        }
+    }
+
+    let mut limit_reached = false;
+    let mut more = args.max_solutions != Some(0);
+    while more {
+        match b.generate() {
+            SearchResult::Solution(mapping) => more = record(mapping)?,
+            SearchResult::Exhausted => break,
+            SearchResult::LimitReached => {
+                limit_reached = true;
+                break;
+            }
+        }
+    }
+
+    if let Some(ref path) = args.nogoods {
+        json::write_nogoods(path, domain, b.nogoods())
+            .context(format!("Could not write nogoods to {}", path))?;
+    }
+
+    Ok(Search {
+        limit_reached,
+        stats: b.stats().clone(),
+        num_nogoods: b.nogoods().len(),
+    })
+}
+
+/// Like search_sequential, on args.threads threads. The threads learn their
+/// nogoods separately and cannot be traced.
+fn search_parallel<F>(domain: &Domain,
+                      args: &cli::SolveArgs,
+                      pins: Vec<Assignment>,
+                      configure: &F,
+                      record: &mut dyn FnMut(Table<Key, Num<Value>>) -> Result<bool>)
+                      -> Result<Search>
+    where F: Fn(&mut Backtracker) + Sync
+{
+    if args.nogoods.is_some() || args.trace.is_some() || args.trace_file.is_some() ||
+        args.limits.nodes.is_some() || args.limits.backtracks.is_some()
+    {
+        bail!("--threads cannot be combined with --nogoods, --trace, --trace-file, \
+               --max-nodes or --max-backtracks");
+    }
+
+    let mut solver = ParallelSolver::new(domain, args.threads);
+    solver.set_configure(Box::new(move |b| configure(b)));
+    solver.set_time_limit(args.limits.time);
+    solver.pin(pins);
+
+    let mut error = None;
+    let mut result = ParallelResult::Exhausted;
+    if args.max_solutions != Some(0) {
+        result = solver.run(|mapping| match record(mapping) {
+            Ok(more) => more,
+            Err(err) => {
+                error = Some(err);
+                false
+            }
+        });
+    }
+    if let Some(err) = error {
+        return Err(err);
+    }
+
+    Ok(Search {
+        limit_reached: result == ParallelResult::LimitReached,
+        stats: solver.stats().clone(),
+        num_nogoods: solver.num_nogoods(),
+    })
+}
+
+fn read_domain(path: &str) -> Result<Domain> {
+    let (domain, compiled) = binary::read_config(path)
+        .context(format!("Could not parse domain {}", path))?;
+    match compiled {
+        binary::Compiled::Used | binary::Compiled::Missing => (),
+        binary::Compiled::OutOfDate =>
+            eprintln!("ignoring {}: out of date", binary::compiled_path(path)),
+        binary::Compiled::Unreadable(err) =>
+            eprintln!("ignoring {}: {}", binary::compiled_path(path), err),
+    }
+    Ok(domain)
+}
+
+/// The pins from the pins file and the command line, where the latter take
+/// precedence.
+fn read_pins(domain: &Domain, args: &cli::SolveArgs) -> Result<Vec<Assignment>> {
+    let mut pins = match args.pins {
+        Some(ref path) => json::read_pins(path, domain)
+            .context(format!("Could not read pins from {}", path))?,
+        None => Vec::new(),
+    };
+    let flags = json::resolve_pins(&args.pin, domain)?;
+    pins.retain(|pin| flags.iter().all(|flag| flag.key_num != pin.key_num));
+    pins.extend(flags);
+    Ok(pins)
+}
+
+fn open_output(path: &Option<String>) -> Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => {
+            let file = File::create(path)
+                .context(format!("Could not create {}", path))?;
+            Box::new(file)
+        }
+        None => Box::new(io::stdout()),
+    })
+}
+
+fn write_solution<W>(out: &mut W,
+                     domain: &Domain,
+                     mapping: &Table<Key, Num<Value>>)
+                     -> Result<()>
+    where W: Write + ?Sized
+{
+    for (key_num, &value_num) in mapping.enumerate() {
+        writeln!(out, "{} = {}", domain.keys[key_num], domain.values[value_num])?;
+    }
+    Ok(())
+}
+
+fn inspect(domain: &Domain) {
+    let num_restricted = domain.key_restrictions.enumerate()
+        .filter(|&(_, restriction)| !restriction.is_trivial())
+        .count();
+
+    let num_constraints = domain.constraint_table.len();
+
+    println!("keys: {}", domain.keys.count().as_usize());
+    println!("values: {}", domain.values.count().as_usize());
+    println!("restricted keys: {}", num_restricted);
+    println!("constrained key pairs: {}", num_constraints);
+    println!("all-different groups: {}", domain.all_different.len());
+    println!("cardinality constraints: {}", domain.cardinality.len());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NUM_OUTPUTS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Run a solve command, returning its exit code and what it wrote. Every
+    /// call writes to an output file of its own.
+    fn solve_output(args: &[&str]) -> (i32, String) {
+        let output_num = NUM_OUTPUTS.fetch_add(1, Ordering::SeqCst);
+        let name = format!("layoutgen_solve_{}_{}.txt", process::id(), output_num);
+        let output = env::temp_dir().join(name);
+        let output = output.to_str().unwrap();
+        let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.extend(vec!["-o".to_string(), output.to_string()]);
+        let args = match cli::parse_args(args.into_iter()).unwrap() {
+            Command::Solve(args) => args,
+            _ => panic!("expected solve command"),
+        };
+
+        let domain = read_domain(&args.domain).unwrap();
+        let status = solve(&domain, &args).unwrap();
+        let contents = fs::read_to_string(output).unwrap();
+        fs::remove_file(output).unwrap();
+        (status.exit_code(), contents)
+    }
+
+    #[test]
+    fn test_count() {
+        // the number of 3-colourings of the Petersen graph
+        assert_eq!(solve_output(&["solve", "--count", "petersen.json"]),
+                   (0, "120\n".to_string()));
+        assert_eq!(solve_output(&["solve", "-c", "-n", "7", "petersen.json"]),
+                   (0, "7\n".to_string()));
+        assert_eq!(solve_output(&["solve", "-c", "--threads", "4", "petersen.json"]),
+                   (0, "120\n".to_string()));
+    }
+}
@@ -15,22 +15,9 @@ SUDOKU = [
 ]
 
 
-def not_restriction(*values):
-    return { "not": list(values) }
-
 def only_restriction(*values):
     return { "only": list(values) }
 
-def neq_restrictor(values):
-    return { v: not_restriction(v) for v in values }
-
-def neq_constraint(origin, target, values):
-    return {
-        "origin": origin,
-        "target": target,
-        "restrictor": neq_restrictor(values),
-    }
-
 def key_restriction(key, *values):
     return {
         "key": key,
@@ -54,25 +41,19 @@ for i, k in enumerate(keys):
         restrictions.append(key_restriction(key_names[i], str(value)))
 
 
-constraints = []
-for (i, k1) in enumerate(keys):
-    for (j, k2) in enumerate(keys):
-        if i == j:
-            continue
-        
-        same_row = (k1[0] == k2[0])
-        same_col = (k1[1] == k2[1])
-        same_square = (k1[0] // 3 == k2[0] // 3) and (k1[1] // 3 == k2[1] // 3)
-
-        if same_row or same_col or same_square:
-            c = neq_constraint(key_names[i], key_names[j], values)
-            constraints.append(c)
+rows = [[n for (n, k) in zip(key_names, keys) if k[0] == i] for i in range(9)]
+cols = [[n for (n, k) in zip(key_names, keys) if k[1] == j] for j in range(9)]
+squares = [
+    [n for (n, k) in zip(key_names, keys) if k[0] // 3 * 3 + k[1] // 3 == s]
+    for s in range(9)
+]
 
 config = {
     "keys": key_names,
     "values": values,
     "restrictions": restrictions,
-    "constraints": constraints,
+    "constraints": [],
+    "all_different": rows + cols + squares,
 }
 
 with open('sudoku.json', 'w') as f: